@@ -0,0 +1,69 @@
+//! Mix node packet throughput
+//!
+//! Pushes a burst of single-hop packets through a node whose strategy holds
+//! every packet for 50ms, and measures how long the whole burst takes to be
+//! delivered. Criterion reports the result in packets per second.
+
+use aether_network::crypto::kyber::PublicKey;
+use aether_network::mixnet::{MixNode, NodeRole, StopAndGoMixing};
+use aether_network::protocols::OutfoxPacket;
+use aether_network::AetherConfig;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const BURST: usize = 5_000;
+const DELAY_MS: u64 = 50;
+
+fn node_throughput(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("mix_node");
+    group.throughput(Throughput::Elements(BURST as u64));
+    group.sample_size(10);
+
+    group.bench_function("fixed_delay_burst", |b| {
+        b.to_async(&runtime).iter_custom(|iters| async move {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let strategy = StopAndGoMixing {
+                    mean_delay_ms: DELAY_MS as f64,
+                    min_delay_ms: DELAY_MS,
+                    max_delay_ms: DELAY_MS,
+                };
+                let node = MixNode::with_strategy(
+                    1,
+                    NodeRole::EntryGateway,
+                    1000,
+                    "127.0.0.1:0".to_string(),
+                    Arc::new(AetherConfig::default()),
+                    Box::new(strategy),
+                )
+                .unwrap();
+
+                // Packet construction is the client's cost, not the node's
+                let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+                for i in 0..BURST {
+                    let packet = OutfoxPacket::new(&(i as u32).to_be_bytes(), &route).unwrap();
+                    node.receive_packet(packet).await;
+                }
+
+                let start = Instant::now();
+                node.start_processing();
+                let mut delivered = 0;
+                while delivered < BURST {
+                    match node.receive_message().await {
+                        Some(_) => delivered += 1,
+                        None => tokio::time::sleep(Duration::from_millis(1)).await,
+                    }
+                }
+                total += start.elapsed();
+            }
+            total
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, node_throughput);
+criterion_main!(benches);
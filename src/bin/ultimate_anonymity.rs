@@ -5,7 +5,7 @@
 use aether_network::{
     proxy::{Socks5Server, HttpProxy},
     protocols::{SphinxBuilder, SphinxProcessor},
-    mixnet::{loopix::LoopixCoverTraffic, node::MixNode, NodeRole},
+    mixnet::{loopix::{channel_sink, LoopixCoverTraffic, LoopixTopology}, node::MixNode, NodeRole},
     privacy::{MixingCascade, TimingNormalizer},
     AetherConfig,
 };
@@ -37,7 +37,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("📦 Sphinx packet system: READY");
     
     // 3. Start Loopix cover traffic
-    let mut loopix = LoopixCoverTraffic::new([0u8; 32]); // Use node's real address
+    // No mix directory yet, so cover traffic waits for a topology to be set
+    let (cover_sink, _cover_packets) = channel_sink();
+    let mut loopix = LoopixCoverTraffic::new([0u8; 32], LoopixTopology::default(), cover_sink); // Use node's real address
     loopix.start().await?;
     
     // 4. Initialize metadata protection cascade
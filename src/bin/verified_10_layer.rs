@@ -3,7 +3,7 @@
 
 use aether_network::{
     proxy::{Socks5Server, HttpProxy},
-    mixnet::loopix::LoopixCoverTraffic,
+    mixnet::loopix::{channel_sink, LoopixCoverTraffic, LoopixTopology},
     privacy::metadata_hiding::MixingCascade,
     AetherConfig,
 };
@@ -79,7 +79,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // PHASE 6: Loopix Cover Traffic
     info!("");
     info!("━━━ PHASE 6: Loopix Cover Traffic ━━━");
-    let mut loopix = LoopixCoverTraffic::new([0xABu8; 32]);
+    // No mix directory yet, so cover traffic waits for a topology to be set
+    let (cover_sink, _cover_packets) = channel_sink();
+    let mut loopix = LoopixCoverTraffic::new([0xABu8; 32], LoopixTopology::default(), cover_sink);
     loopix.start().await?;
     info!("✅ Cover traffic generator active");
     
@@ -20,6 +20,112 @@ pub struct AetherConfig {
     pub mixnet_layers: usize,
     pub poisson_lambda: f64,
     pub cover_traffic_ratio: f64,
+    #[serde(default)]
+    pub mixnet: MixnetConfig,
+}
+
+/// Mix node runtime settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MixnetConfig {
+    /// Maximum replay tags remembered per key epoch; packets beyond it are
+    /// refused until the next rotation
+    pub replay_cache_capacity: usize,
+    /// File to persist the replay cache to (in-memory only if unset)
+    pub replay_cache_path: Option<String>,
+    /// File to write a node's final statistics to on shutdown, as JSON
+    pub stats_path: Option<String>,
+    /// Erasure-coding parity fragments per data fragment for fragmented
+    /// messages (0 disables coding, at most 3)
+    pub fec_redundancy: f64,
+    /// How nodes delay and batch packets
+    pub mixing: MixingConfig,
+    /// Tasks decrypting incoming packets concurrently (0 for one per CPU core)
+    pub processing_workers: usize,
+    /// Most packets held in each of the incoming and outgoing queues
+    pub queue_capacity: usize,
+    /// Which packets a full queue gives up
+    pub drop_policy: DropPolicy,
+    /// Packets per second accepted from one peer address (0 for no limit)
+    pub peer_rate_limit: f64,
+    /// Packets a peer may send back-to-back before its rate limit applies
+    pub peer_burst: usize,
+    /// Seconds each mix key is used for; epochs are counted from the Unix epoch
+    pub epoch_length_secs: u64,
+    /// Seconds after a rotation during which the previous epoch's key is
+    /// still accepted, for packets already in flight
+    pub epoch_grace_secs: u64,
+}
+
+/// Mixing strategy selection
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum MixingConfig {
+    /// Exponential per-packet delays with mean `poisson_lambda` milliseconds
+    StopAndGo,
+    /// Flush the whole pool every `batch_interval_ms`
+    Timed {
+        /// Milliseconds between flushes
+        batch_interval_ms: u64,
+    },
+    /// Flush the whole pool once it holds `threshold` packets
+    Threshold {
+        /// Pool size that triggers a flush
+        threshold: usize,
+    },
+    /// Flush the whole pool at the threshold or the interval, whichever comes first
+    ThresholdOrTimed {
+        /// Pool size that triggers a flush
+        threshold: usize,
+        /// Longest time between flushes
+        batch_interval_ms: u64,
+    },
+    /// Binomial/Cottrell pool: keep `min_pool` packets back, send each of the
+    /// rest with some probability every interval
+    BinomialPool {
+        /// Milliseconds between flush rounds
+        batch_interval_ms: u64,
+        /// Packets always kept in the pool
+        min_pool: usize,
+        /// Expected fraction of the excess sent per round
+        send_fraction: f64,
+    },
+}
+
+/// What a bounded packet queue does under pressure
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum DropPolicy {
+    /// Refuse packets that arrive while the queue is full
+    DropNewest,
+    /// Refuse arriving packets with a probability that rises linearly from 0
+    /// at `min_fill` of capacity to 1 at a full queue
+    RandomEarly {
+        /// Fraction of capacity below which nothing is dropped
+        min_fill: f64,
+    },
+    /// When full, evict the oldest queued cover packet to make room; refuse
+    /// the arriving packet if no cover packet is queued
+    CoverFirst,
+}
+
+impl Default for MixnetConfig {
+    fn default() -> Self {
+        Self {
+            replay_cache_capacity: 1_000_000,
+            replay_cache_path: None,
+            stats_path: None,
+            fec_redundancy: 0.0,
+            mixing: MixingConfig::StopAndGo,
+            processing_workers: 0,
+            queue_capacity: 10_000,
+            drop_policy: DropPolicy::DropNewest,
+            peer_rate_limit: 0.0,
+            peer_burst: 100,
+            epoch_length_secs: 3600,
+            epoch_grace_secs: 300,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -140,6 +246,7 @@ impl Default for AetherConfig {
             mixnet_layers: 5,
             poisson_lambda: 2.0,
             cover_traffic_ratio: 0.1,
+            mixnet: MixnetConfig::default(),
         }
     }
 }
@@ -5,6 +5,32 @@ pub enum CipherSuite {
     Kyber1024Aes256Gcm,
     Dilithium5ChaCha20,
     FrodoKemAes256Gcm,
+    /// Kyber-1024 KEM per hop, XChaCha20-Poly1305 payload AEAD and
+    /// ChaCha20/Lioness layer encryption, as used by Outfox v1 packets
+    Kyber1024XChaCha20,
+}
+
+impl CipherSuite {
+    /// Identifier of this suite in wire formats
+    pub fn id(&self) -> u8 {
+        match self {
+            CipherSuite::Kyber1024Aes256Gcm => 1,
+            CipherSuite::Dilithium5ChaCha20 => 2,
+            CipherSuite::FrodoKemAes256Gcm => 3,
+            CipherSuite::Kyber1024XChaCha20 => 4,
+        }
+    }
+
+    /// Look up a suite by its wire identifier
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(CipherSuite::Kyber1024Aes256Gcm),
+            2 => Some(CipherSuite::Dilithium5ChaCha20),
+            3 => Some(CipherSuite::FrodoKemAes256Gcm),
+            4 => Some(CipherSuite::Kyber1024XChaCha20),
+            _ => None,
+        }
+    }
 }
 
 pub struct AgilityManager {
@@ -15,7 +41,7 @@ pub struct AgilityManager {
 impl AgilityManager {
     pub fn new() -> Self {
         Self {
-            current_cipher: CipherSuite::Kyber1024Aes256Gcm,
+            current_cipher: CipherSuite::Kyber1024XChaCha20,
             pending_cipher: None,
         }
     }
@@ -16,7 +16,7 @@ pub struct HybridKeyPair {
     dilithium_sk: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct HybridPublicKey {
     /// Ed25519 classical verifying key
     ed25519_pk: Vec<u8>,
@@ -24,7 +24,7 @@ pub struct HybridPublicKey {
     dilithium_pk: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HybridSignature {
     /// Ed25519 signature (64 bytes)
     ed25519_sig: Vec<u8>,
@@ -88,9 +88,18 @@ impl HybridSigner {
         signature: &HybridSignature,
         public_key: &HybridPublicKey,
     ) -> Result<(), String> {
+        public_key.verify(message, signature)
+    }
+}
+
+impl HybridPublicKey {
+    /// Verify that `signature` is a hybrid signature over `message` by this key
+    ///
+    /// Needs no signing key, so anyone holding the public key can check it.
+    pub fn verify(&self, message: &[u8], signature: &HybridSignature) -> Result<(), String> {
         // Verify Ed25519 signature
         let ed25519_pk = VerifyingKey::from_bytes(
-            public_key.ed25519_pk.as_slice().try_into()
+            self.ed25519_pk.as_slice().try_into()
                 .map_err(|_| "Invalid Ed25519 public key")?
         ).map_err(|_| "Invalid Ed25519 public key format")?;
 
@@ -103,14 +112,18 @@ impl HybridSigner {
             .map_err(|_| "Ed25519 signature verification failed")?;
 
         // Verify Dilithium5 signature
-        let dilithium_pk = dilithium5::PublicKey::from_bytes(&public_key.dilithium_pk)
+        let dilithium_pk = dilithium5::PublicKey::from_bytes(&self.dilithium_pk)
             .map_err(|_| "Invalid Dilithium public key")?;
 
         let dilithium_msg = dilithium5::SignedMessage::from_bytes(&signature.dilithium_sig)
             .map_err(|_| "Invalid Dilithium signature")?;
 
-        dilithium5::open(&dilithium_msg, &dilithium_pk)
+        // The signed message carries its own copy of what was signed, which must be ours
+        let opened = dilithium5::open(&dilithium_msg, &dilithium_pk)
             .map_err(|_| "Dilithium signature verification failed")?;
+        if opened != message {
+            return Err("Dilithium signature is for a different message".to_string());
+        }
 
         tracing::info!("✅ HYBRID SIGNATURE VERIFIED: Both Ed25519 AND Dilithium5 valid");
         Ok(())
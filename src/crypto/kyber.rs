@@ -3,13 +3,13 @@
 use pqcrypto_kyber::kyber1024::*;
 use pqcrypto_traits::kem::{PublicKey as PQPublicKey, SecretKey as PQSecretKey, SharedSecret as PQSharedSecret, Ciphertext as PQCiphertext};
 use crate::error::{AetherError, Result};
+use zeroize::Zeroize;
 
 /// Wrapper for Kyber-1024 public key
 #[derive(Clone)]
 pub struct PublicKey(pqcrypto_kyber::kyber1024::PublicKey);
 
-/// Wrapper for Kyber-1024 secret key
-#[derive(Clone)]
+/// Wrapper for Kyber-1024 secret key, erased from memory when dropped
 pub struct SecretKey(pqcrypto_kyber::kyber1024::SecretKey);
 
 ///Wrapper for Kyber-1024 ciphertext
@@ -21,7 +21,6 @@ pub struct Ciphertext(pqcrypto_kyber::kyber1024::Ciphertext);
 pub struct SharedSecret(pqcrypto_kyber::kyber1024::SharedSecret);
 
 /// Key pair structure
-#[derive(Clone)]
 pub struct KeyPair {
     pub public_key: PublicKey,
     pub secret_key: SecretKey,
@@ -66,6 +65,29 @@ impl SecretKey {
     }
 }
 
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        // pqcrypto keys are plain byte arrays without zeroize support
+        let size = std::mem::size_of::<pqcrypto_kyber::kyber1024::SecretKey>();
+        debug_assert_eq!(size, secret_key_size());
+        // SAFETY: the key is a `[u8; N]` newtype, valid for any byte pattern
+        #[allow(unsafe_code)]
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                (&mut self.0 as *mut pqcrypto_kyber::kyber1024::SecretKey).cast::<u8>(),
+                size,
+            )
+        };
+        bytes.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl SharedSecret {
     /// Get the raw bytes of the shared secret
     pub fn as_bytes(&self) -> &[u8] {
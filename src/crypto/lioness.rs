@@ -0,0 +1,126 @@
+//! Lioness wide-block cipher (Anderson & Biham)
+//!
+//! Encrypts a whole block at once so that changing any ciphertext bit
+//! garbles the entire plaintext. Built from ChaCha20 and keyed BLAKE3.
+
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use crate::error::{AetherError, Result};
+
+/// Size of the left half of the block (one stream cipher key)
+const LEFT_SIZE: usize = 32;
+
+/// Minimum block size accepted by Lioness
+pub const MIN_BLOCK_SIZE: usize = LEFT_SIZE + 1;
+
+/// Round keys derived from a single 32-byte key
+struct RoundKeys([[u8; 32]; 4]);
+
+impl RoundKeys {
+    fn derive(key: &[u8; 32]) -> Self {
+        let mut keys = [[0u8; 32]; 4];
+        for (i, round_key) in keys.iter_mut().enumerate() {
+            let mut hasher = blake3::Hasher::new_derive_key("aether lioness v1 round key");
+            hasher.update(key);
+            hasher.update(&[i as u8]);
+            *round_key = *hasher.finalize().as_bytes();
+        }
+        Self(keys)
+    }
+}
+
+/// Encrypt `block` in place
+pub fn lioness_encrypt(key: &[u8; 32], block: &mut [u8]) -> Result<()> {
+    check_block(block)?;
+    let keys = RoundKeys::derive(key);
+    let (left, right) = block.split_at_mut(LEFT_SIZE);
+
+    stream_round(&keys.0[0], left, right);
+    hash_round(&keys.0[1], left, right);
+    stream_round(&keys.0[2], left, right);
+    hash_round(&keys.0[3], left, right);
+    Ok(())
+}
+
+/// Decrypt `block` in place
+pub fn lioness_decrypt(key: &[u8; 32], block: &mut [u8]) -> Result<()> {
+    check_block(block)?;
+    let keys = RoundKeys::derive(key);
+    let (left, right) = block.split_at_mut(LEFT_SIZE);
+
+    hash_round(&keys.0[3], left, right);
+    stream_round(&keys.0[2], left, right);
+    hash_round(&keys.0[1], left, right);
+    stream_round(&keys.0[0], left, right);
+    Ok(())
+}
+
+fn check_block(block: &[u8]) -> Result<()> {
+    if block.len() < MIN_BLOCK_SIZE {
+        return Err(AetherError::Crypto(format!(
+            "Lioness block must be at least {} bytes",
+            MIN_BLOCK_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// R ^= S(L ^ K)
+fn stream_round(round_key: &[u8; 32], left: &[u8], right: &mut [u8]) {
+    let mut stream_key = [0u8; 32];
+    for (k, (l, r)) in stream_key.iter_mut().zip(left.iter().zip(round_key)) {
+        *k = l ^ r;
+    }
+    let mut cipher = ChaCha20::new(&stream_key.into(), &[0u8; 12].into());
+    cipher.apply_keystream(right);
+}
+
+/// L ^= H_K(R)
+fn hash_round(round_key: &[u8; 32], left: &mut [u8], right: &[u8]) {
+    let digest = blake3::keyed_hash(round_key, right);
+    for (l, d) in left.iter_mut().zip(digest.as_bytes()) {
+        *l ^= d;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::symmetric::generate_key;
+
+    #[test]
+    fn test_round_trip() {
+        let key = generate_key();
+        let plaintext: Vec<u8> = (0..512).map(|i| i as u8).collect();
+
+        let mut block = plaintext.clone();
+        lioness_encrypt(&key, &mut block).unwrap();
+        assert_ne!(block, plaintext);
+
+        lioness_decrypt(&key, &mut block).unwrap();
+        assert_eq!(block, plaintext);
+    }
+
+    #[test]
+    fn test_single_bit_garbles_whole_block() {
+        let key = generate_key();
+        let plaintext = vec![0u8; 512];
+
+        let mut block = plaintext.clone();
+        lioness_encrypt(&key, &mut block).unwrap();
+        block[400] ^= 1;
+        lioness_decrypt(&key, &mut block).unwrap();
+
+        // Both halves are affected, not just the flipped byte
+        assert_ne!(&block[..LEFT_SIZE], &plaintext[..LEFT_SIZE]);
+        let unchanged = block.iter().zip(&plaintext).filter(|(a, b)| a == b).count();
+        assert!(unchanged < 32);
+    }
+
+    #[test]
+    fn test_short_block_rejected() {
+        let key = generate_key();
+        let mut block = [0u8; LEFT_SIZE];
+        assert!(lioness_encrypt(&key, &mut block).is_err());
+    }
+}
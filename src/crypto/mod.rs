@@ -4,6 +4,7 @@
 
 pub mod kyber;
 pub mod symmetric;
+pub mod lioness;
 pub mod hash;
 pub mod signatures;
 pub mod sharding;
@@ -14,6 +15,7 @@ pub mod simd_crypto;
 
 pub use kyber::{KeyPair, PublicKey, SecretKey, encapsulate, decapsulate};
 pub use symmetric::{encrypt_aead, decrypt_aead};
+pub use lioness::{lioness_encrypt, lioness_decrypt};
 pub use hash::{blake3_hash, derive_key};
 pub use signatures::{sign_message, verify_signature};
 pub use sharding::IdentitySharder;
@@ -20,7 +20,7 @@ pub enum AetherError {
     Network(String),
     
     /// Packet processing error (e.g., invalid header or malformed payload)
-    Packet(String),
+    Packet(PacketError),
     
     /// Routing error (e.g., no path identified for the destination)
     Routing(String),
@@ -38,6 +38,44 @@ pub enum AetherError {
     InvalidState(String),
 }
 
+/// Reasons a packet is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketError {
+    /// Input is not exactly one packet long
+    InvalidLength {
+        /// Length the wire format requires
+        expected: usize,
+        /// Length that was received
+        actual: usize,
+    },
+
+    /// Wire format version this build does not understand
+    UnsupportedVersion(u8),
+
+    /// Cipher-suite identifier that is unknown or not usable for this packet format
+    UnsupportedCipherSuite(u8),
+
+    /// Header or payload failed authentication
+    AuthenticationFailed,
+
+    /// Any other malformed or unusable packet
+    Malformed(String),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes, got {}", expected, actual)
+            }
+            PacketError::UnsupportedVersion(v) => write!(f, "unsupported wire version {}", v),
+            PacketError::UnsupportedCipherSuite(id) => write!(f, "unsupported cipher suite {}", id),
+            PacketError::AuthenticationFailed => write!(f, "authentication failed"),
+            PacketError::Malformed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
 impl fmt::Display for AetherError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -62,6 +100,12 @@ impl From<std::io::Error> for AetherError {
     }
 }
 
+impl From<PacketError> for AetherError {
+    fn from(err: PacketError) -> Self {
+        AetherError::Packet(err)
+    }
+}
+
 impl From<serde_json::Error> for AetherError {
     fn from(err: serde_json::Error) -> Self {
         AetherError::Serialization(err.to_string())
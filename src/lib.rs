@@ -33,7 +33,7 @@ pub mod stealth;
 pub mod hardware;
 
 pub use config::AetherConfig;
-pub use error::{AetherError, Result};
+pub use error::{AetherError, PacketError, Result};
 
 /// Version of the Aether protocol
 pub const PROTOCOL_VERSION: &str = "0.2.0";
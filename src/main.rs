@@ -32,6 +32,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // 4. Create and Start Nodes for a 5-layer Mixnet
     let mut nodes = Vec::new();
+    let mut handles = Vec::new();
     for layer in 1..=5 {
         let role = match layer {
             1 => NodeRole::EntryGateway,
@@ -48,10 +49,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arc::clone(&config),
         )?);
         
-        node.run().await?;
+        handles.push(node.run().await?);
         nodes.push(node);
     }
     
+    // Let every node reach the others over TCP
+    for node in &nodes {
+        for peer in &nodes {
+            node.add_peer(&peer.info).await;
+        }
+    }
+    
     info!("🚀 Network is LIVE and operational.");
     info!("🛡️  Post-Quantum Security Active (Kyber-1024)");
     info!("------------------------------------------");
@@ -81,17 +89,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Err(e) => warn!("❌ Failed to create packet: {}", e),
         }
 
-        // Manually route packets between nodes in this simulation
-        // (This simulates the network transport layer synchronously for the demo)
-        for i in 0..nodes.len() - 1 {
-            if let Some(packet) = nodes[i].send_packet().await {
-                // Transfer from layer i to i+1
-                nodes[i+1].receive_packet(packet).await;
-            }
-        }
-        
         // Consume processed packets at the Exit Gateway
-        if let Some(_processed) = nodes[4].send_packet().await {
+        if let Some(_message) = nodes[4].receive_message().await {
             info!("🏁 Packet #{} reached Final Exit Gateway (Layer 5)", packet_count);
         }
 
@@ -105,8 +104,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        // Run continuously with a small delay between packets
-        sleep(Duration::from_millis(2000)).await;
+        // Run continuously with a small delay between packets, until Ctrl-C
+        tokio::select! {
+            _ = sleep(Duration::from_millis(2000)) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
         
         // Advanced: Periodically test ZK proofs to ensure crypto validity
         if packet_count % 10 == 0 {
@@ -117,4 +119,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
+    
+    info!("🛑 Shutting down, draining queued packets...");
+    for handle in handles {
+        let stats = handle.shutdown().await?;
+        info!("   Node drained after {} packets", stats.packets_processed);
+    }
+    Ok(())
 }
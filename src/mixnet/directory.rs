@@ -0,0 +1,442 @@
+//! Network directory
+//!
+//! Clients learn the mixes, their layers, addresses and epoch keys from a
+//! directory document. Each document covers one key epoch. Directory
+//! authorities build it from the same registrations and each sign it
+//! independently. A client accepts it only if a threshold of the
+//! authorities it trusts have signed it, so no single authority can
+//! mislead clients on its own.
+
+use crate::crypto::hybrid_pq::{HybridPublicKey, HybridSignature, HybridSigner};
+use crate::crypto::kyber::PublicKey;
+use crate::error::{AetherError, Result};
+use crate::mixnet::epoch::EpochSchedule;
+use crate::mixnet::node::NodeInfo;
+use crate::mixnet::topology::Topology;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Domain separator for directory signatures
+const SIGNATURE_DOMAIN: &[u8] = b"aether-directory-document-v1";
+
+/// Every known node for one key epoch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirectoryDocument {
+    pub epoch: u64,
+
+    /// Unix seconds from which the document applies
+    pub valid_after: u64,
+
+    /// Unix seconds after which the document is stale
+    pub valid_until: u64,
+
+    /// Nodes sorted by id, each with the keys it published for this epoch
+    /// and the next
+    pub nodes: Vec<NodeInfo>,
+}
+
+impl DirectoryDocument {
+    /// Hash of the canonical encoding, which is what authorities sign
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let encoded = bincode::serialize(self).map_err(|e| AetherError::Serialization(e.to_string()))?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(SIGNATURE_DOMAIN);
+        hasher.update(&encoded);
+        Ok(*hasher.finalize().as_bytes())
+    }
+
+    /// Sign the document as one authority
+    pub fn sign(&self, authority: &HybridSigner) -> Result<AuthoritySignature> {
+        Ok(AuthoritySignature {
+            authority: authority.public_key(),
+            signature: authority.sign(&self.digest()?),
+        })
+    }
+
+    /// Whether the document applies at `time`
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        let now = unix_secs(time);
+        self.valid_after <= now && now < self.valid_until
+    }
+
+    /// The node with `id`
+    pub fn node(&self, id: &[u8; 32]) -> Option<&NodeInfo> {
+        self.nodes.iter().find(|node| &node.id == id)
+    }
+
+    /// Nodes serving in `layer` (1-based)
+    pub fn layer(&self, layer: usize) -> Vec<&NodeInfo> {
+        self.nodes.iter().filter(|node| node.layer == layer).collect()
+    }
+}
+
+/// One authority's signature over a directory document
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthoritySignature {
+    /// Public key of the signing authority
+    pub authority: HybridPublicKey,
+    /// Signature over the document's digest
+    pub signature: HybridSignature,
+}
+
+/// A directory document with the authority signatures collected for it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedDirectory {
+    /// The signed document
+    pub document: DirectoryDocument,
+    /// Signatures collected so far, at most one per authority
+    pub signatures: Vec<AuthoritySignature>,
+}
+
+impl SignedDirectory {
+    /// Wrap a document that has no signatures yet
+    pub fn new(document: DirectoryDocument) -> Self {
+        Self { document, signatures: Vec::new() }
+    }
+
+    /// Add a signature collected from an authority, after checking it
+    ///
+    /// A second signature from the same authority replaces the first.
+    pub fn add_signature(&mut self, signature: AuthoritySignature) -> Result<()> {
+        signature
+            .authority
+            .verify(&self.document.digest()?, &signature.signature)
+            .map_err(AetherError::Crypto)?;
+        self.signatures.retain(|existing| existing.authority != signature.authority);
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    /// Encode for distribution or caching
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| AetherError::Serialization(e.to_string()))
+    }
+
+    /// Decode a directory; its signatures are not checked here
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).map_err(|e| AetherError::Serialization(e.to_string()))
+    }
+}
+
+/// Assembles the directory document of one epoch on an authority
+pub struct DirectoryBuilder {
+    epoch: u64,
+    schedule: EpochSchedule,
+    nodes: BTreeMap<[u8; 32], NodeInfo>,
+}
+
+impl DirectoryBuilder {
+    /// Start the document for `epoch` of `schedule`
+    pub fn new(epoch: u64, schedule: EpochSchedule) -> Self {
+        Self { epoch, schedule, nodes: BTreeMap::new() }
+    }
+
+    /// List a node, replacing an earlier entry with the same id
+    ///
+    /// The node must have published a valid key for the epoch.
+    pub fn add_node(&mut self, node: NodeInfo) -> Result<&mut Self> {
+        let key = node
+            .epoch_keys
+            .iter()
+            .find(|key| key.epoch == self.epoch)
+            .ok_or_else(|| {
+                AetherError::Config(format!(
+                    "Node {} has no key for epoch {}",
+                    hex::encode(&node.id[..8]),
+                    self.epoch
+                ))
+            })?;
+        PublicKey::from_bytes(&key.public_key_bytes)?;
+        self.nodes.insert(node.id, node);
+        Ok(self)
+    }
+
+    /// List every node of `topology`, in the layer it assigns
+    pub fn add_topology(&mut self, topology: &Topology) -> Result<&mut Self> {
+        if topology.epoch != self.epoch {
+            return Err(AetherError::Config(format!(
+                "Topology is for epoch {}, directory for epoch {}",
+                topology.epoch, self.epoch
+            )));
+        }
+        for node in topology.layers.iter().flatten() {
+            self.add_node(node.info.clone())?;
+        }
+        Ok(self)
+    }
+
+    /// Finish the document
+    ///
+    /// It applies from the start of the epoch until the previous epoch's
+    /// key grace window would end in the epoch after.
+    pub fn build(&self) -> DirectoryDocument {
+        let valid_after = unix_secs(self.schedule.start(self.epoch));
+        let valid_until = unix_secs(self.schedule.start(self.epoch + 1) + self.schedule.grace());
+        DirectoryDocument {
+            epoch: self.epoch,
+            valid_after,
+            valid_until,
+            nodes: self.nodes.values().cloned().collect(),
+        }
+    }
+}
+
+/// Checks directories against a client's trusted authorities
+#[derive(Clone, Debug)]
+pub struct DirectoryVerifier {
+    authorities: Vec<HybridPublicKey>,
+    threshold: usize,
+}
+
+impl DirectoryVerifier {
+    /// Accept directories signed by at least `threshold` of `authorities`
+    ///
+    /// Each authority may be listed once, so the threshold counts distinct keys.
+    pub fn new(authorities: Vec<HybridPublicKey>, threshold: usize) -> Result<Self> {
+        let mut distinct = HashSet::new();
+        if !authorities.iter().all(|authority| distinct.insert(authority)) {
+            return Err(AetherError::Config("Directory authority listed more than once".to_string()));
+        }
+        if threshold == 0 || threshold > authorities.len() {
+            return Err(AetherError::Config(format!(
+                "Directory threshold {} must be between 1 and {}",
+                threshold,
+                authorities.len()
+            )));
+        }
+        Ok(Self { authorities, threshold })
+    }
+
+    /// Check the signatures and validity period of `directory` at `now`
+    ///
+    /// Only valid signatures from distinct trusted authorities count
+    /// towards the threshold.
+    pub fn verify<'a>(&self, directory: &'a SignedDirectory, now: SystemTime) -> Result<&'a DirectoryDocument> {
+        let document = &directory.document;
+        if !document.is_valid_at(now) {
+            return Err(AetherError::InvalidState(format!(
+                "Directory for epoch {} is not valid now",
+                document.epoch
+            )));
+        }
+
+        let digest = document.digest()?;
+        let signers: HashSet<&HybridPublicKey> = directory
+            .signatures
+            .iter()
+            .filter(|signed| self.authorities.contains(&signed.authority))
+            .filter(|signed| signed.authority.verify(&digest, &signed.signature).is_ok())
+            .map(|signed| &signed.authority)
+            .collect();
+
+        if signers.len() < self.threshold {
+            return Err(AetherError::Crypto(format!(
+                "Directory has {} of the {} required authority signatures",
+                signers.len(),
+                self.threshold
+            )));
+        }
+        Ok(document)
+    }
+}
+
+/// A client's verified copy of the latest directory
+pub struct DirectoryCache {
+    verifier: DirectoryVerifier,
+    current: Option<SignedDirectory>,
+    /// Where to keep the directory across restarts, if anywhere
+    path: Option<PathBuf>,
+}
+
+impl DirectoryCache {
+    /// Create an empty in-memory cache
+    pub fn new(verifier: DirectoryVerifier) -> Self {
+        Self { verifier, current: None, path: None }
+    }
+
+    /// Create a cache persisted at `path`, loading a stored directory if it
+    /// still verifies
+    ///
+    /// A stored directory that cannot be read, decoded or verified is
+    /// ignored, and the cache starts empty.
+    pub fn with_persistence(verifier: DirectoryVerifier, path: impl AsRef<Path>) -> Result<Self> {
+        let mut cache = Self::new(verifier);
+        cache.path = Some(path.as_ref().to_path_buf());
+
+        if path.as_ref().exists() {
+            let loaded = fs::read(path.as_ref())
+                .map_err(AetherError::from)
+                .and_then(|bytes| SignedDirectory::from_bytes(&bytes))
+                .and_then(|stored| {
+                    cache.verifier.verify(&stored, SystemTime::now())?;
+                    Ok(stored)
+                });
+            match loaded {
+                Ok(stored) => cache.current = Some(stored),
+                Err(e) => tracing::warn!("Ignoring cached directory: {}", e),
+            }
+        }
+
+        Ok(cache)
+    }
+
+    /// Take a directory fetched at `now`, returning whether it replaced the
+    /// cached one
+    ///
+    /// It must verify, and an older or same epoch than the cached directory
+    /// is ignored so a stale copy cannot roll clients back.
+    pub fn update(&mut self, directory: SignedDirectory, now: SystemTime) -> Result<bool> {
+        self.verifier.verify(&directory, now)?;
+        if self.current.as_ref().is_some_and(|current| current.document.epoch >= directory.document.epoch) {
+            return Ok(false);
+        }
+
+        self.current = Some(directory);
+        self.persist()?;
+        Ok(true)
+    }
+
+    /// The cached directory, if it is still valid at `now`
+    pub fn current(&self, now: SystemTime) -> Option<&DirectoryDocument> {
+        self.current
+            .as_ref()
+            .map(|current| &current.document)
+            .filter(|document| document.is_valid_at(now))
+    }
+
+    /// Write the cached directory to its persistence path, if one is configured
+    pub fn persist(&self) -> Result<()> {
+        let (Some(path), Some(current)) = (&self.path, &self.current) else {
+            return Ok(());
+        };
+
+        // Write then rename so a crash never leaves a truncated file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, current.to_bytes()?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AetherConfig;
+    use crate::mixnet::node::{MixNode, NodeRole};
+    use std::sync::Arc;
+
+    /// A directory for the current epoch listing one node per layer
+    fn document() -> (DirectoryDocument, EpochSchedule) {
+        let config = Arc::new(AetherConfig::default());
+        let schedule = EpochSchedule::from_config(&config.mixnet);
+        let nodes: Vec<MixNode> = (1..=3)
+            .map(|layer| {
+                MixNode::new(layer, NodeRole::MixNode, 1000, format!("127.0.0.1:{}", 9200 + layer), Arc::clone(&config))
+                    .unwrap()
+            })
+            .collect();
+
+        let mut builder = DirectoryBuilder::new(schedule.epoch_at(SystemTime::now()), schedule);
+        for node in &nodes {
+            builder.add_node(node.published_info()).unwrap();
+        }
+        (builder.build(), schedule)
+    }
+
+    #[test]
+    fn test_threshold_of_trusted_authorities_required() {
+        let authorities: Vec<HybridSigner> = (0..3).map(|_| HybridSigner::new()).collect();
+        let verifier = DirectoryVerifier::new(authorities.iter().map(|a| a.public_key()).collect(), 2).unwrap();
+        let (document, _) = document();
+        let now = SystemTime::now();
+
+        let mut directory = SignedDirectory::new(document.clone());
+        directory.add_signature(document.sign(&authorities[0]).unwrap()).unwrap();
+        // Signing twice, or by an untrusted party, does not count
+        directory.add_signature(document.sign(&authorities[0]).unwrap()).unwrap();
+        directory.add_signature(document.sign(&HybridSigner::new()).unwrap()).unwrap();
+        assert!(verifier.verify(&directory, now).is_err());
+
+        directory.add_signature(document.sign(&authorities[2]).unwrap()).unwrap();
+        let verified = verifier.verify(&directory, now).unwrap();
+        assert_eq!(verified.nodes.len(), 3);
+        assert_eq!(verified.layer(2).len(), 1);
+        let first = &verified.nodes[0];
+        assert_eq!(verified.node(&first.id).unwrap().address, first.address);
+
+        // Any change to the listing breaks every signature
+        let mut tampered = directory.clone();
+        tampered.document.nodes[0].address = "203.0.113.1:9000".to_string();
+        assert!(verifier.verify(&tampered, now).is_err());
+
+        // A signature over another document is refused on collection
+        let mut other = document.clone();
+        other.nodes.pop();
+        assert!(directory.add_signature(other.sign(&authorities[1]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_authorities_rejected() {
+        let authority = HybridSigner::new().public_key();
+        let other = HybridSigner::new().public_key();
+
+        // A repeated key would let one authority count towards the threshold twice
+        let result = DirectoryVerifier::new(vec![authority.clone(), other, authority], 2);
+        assert!(matches!(result, Err(AetherError::Config(_))));
+    }
+
+    #[test]
+    fn test_builder_requires_key_for_epoch() {
+        let (document, schedule) = document();
+        let mut node = document.nodes[0].clone();
+        node.epoch_keys.retain(|key| key.epoch != document.epoch);
+
+        let mut builder = DirectoryBuilder::new(document.epoch, schedule);
+        assert!(builder.add_node(node).is_err());
+    }
+
+    #[test]
+    fn test_cache_refuses_rollback_and_persists() {
+        let authority = HybridSigner::new();
+        let verifier = DirectoryVerifier::new(vec![authority.public_key()], 1).unwrap();
+        let (document, schedule) = document();
+        let now = SystemTime::now();
+
+        let signed = |document: &DirectoryDocument| {
+            let mut directory = SignedDirectory::new(document.clone());
+            directory.add_signature(document.sign(&authority).unwrap()).unwrap();
+            directory
+        };
+
+        let path = std::env::temp_dir().join(format!("aether-directory-{}.bin", rand::random::<u64>()));
+        let mut cache = DirectoryCache::with_persistence(verifier.clone(), &path).unwrap();
+        assert!(cache.current(now).is_none());
+        assert!(cache.update(signed(&document), now).unwrap());
+
+        // An older directory is ignored even though it verifies
+        let mut older = document.clone();
+        older.epoch -= 1;
+        assert!(!cache.update(signed(&older), now).unwrap());
+        assert_eq!(cache.current(now).unwrap().epoch, document.epoch);
+
+        // It expires with its epoch, and survives a restart until then
+        let expired = schedule.start(document.epoch + 1) + schedule.grace();
+        assert!(cache.current(expired).is_none());
+        let restored = DirectoryCache::with_persistence(verifier.clone(), &path).unwrap();
+        assert_eq!(restored.current(now).unwrap().epoch, document.epoch);
+
+        // A damaged file leaves the client starting without a directory
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let damaged = DirectoryCache::with_persistence(verifier, &path).unwrap();
+        assert!(damaged.current(now).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+//! Mix key epochs
+//!
+//! A mix node changes its Kyber key every epoch, so a key compromised later
+//! cannot open packets recorded earlier, and replay tags only need to be
+//! kept for as long as a key is valid. Epochs are counted from the Unix
+//! epoch, so nodes and clients agree on them without coordinating.
+//!
+//! The next epoch's public key is published a whole epoch ahead, letting
+//! senders build packets that arrive after a rotation. After rotating, the
+//! previous key is still accepted for a grace window covering packets in
+//! flight, then its secret is erased.
+
+use crate::config::MixnetConfig;
+use crate::crypto::kyber::{KeyPair, PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Epoch length and rotation grace window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpochSchedule {
+    length: Duration,
+    grace: Duration,
+}
+
+impl EpochSchedule {
+    /// Epochs of `length`, accepting the previous key for `grace` after each
+    /// rotation; the grace window never outlasts an epoch
+    pub fn new(length: Duration, grace: Duration) -> Self {
+        let length = length.max(Duration::from_secs(1));
+        Self { length, grace: grace.min(length) }
+    }
+
+    /// The schedule configured for mix nodes
+    pub fn from_config(config: &MixnetConfig) -> Self {
+        Self::new(
+            Duration::from_secs(config.epoch_length_secs),
+            Duration::from_secs(config.epoch_grace_secs),
+        )
+    }
+
+    /// Length of one epoch
+    pub fn length(&self) -> Duration {
+        self.length
+    }
+
+    /// How long the previous key stays valid after a rotation
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// Epoch containing `time`
+    pub fn epoch_at(&self, time: SystemTime) -> u64 {
+        let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        since_unix.as_secs() / self.length.as_secs()
+    }
+
+    /// When `epoch` begins
+    pub fn start(&self, epoch: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(epoch.saturating_mul(self.length.as_secs()))
+    }
+}
+
+/// A public mix key and the epoch it is valid for, as published to peers
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EpochKey {
+    /// Epoch the key is valid for
+    pub epoch: u64,
+    /// Kyber-1024 public key
+    pub public_key_bytes: Vec<u8>,
+}
+
+/// Key pair of one epoch
+struct EpochSecret {
+    epoch: u64,
+    key_pair: KeyPair,
+}
+
+impl EpochSecret {
+    fn generate(epoch: u64) -> Self {
+        Self { epoch, key_pair: KeyPair::generate() }
+    }
+
+    fn public(&self) -> EpochKey {
+        EpochKey {
+            epoch: self.epoch,
+            public_key_bytes: self.key_pair.public_key.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// A node's mix keys: the current epoch's, the next one's, and the
+/// previous one's while its grace window lasts
+///
+/// Secret keys are erased from memory as soon as they are dropped.
+pub struct EpochKeys {
+    schedule: EpochSchedule,
+    current: EpochSecret,
+    next: EpochSecret,
+    previous: Option<EpochSecret>,
+    /// Set once every secret key has been erased
+    erased: bool,
+}
+
+impl EpochKeys {
+    /// Generate keys for the epoch containing `now` and the one after it
+    pub fn new(schedule: EpochSchedule, now: SystemTime) -> Self {
+        let epoch = schedule.epoch_at(now);
+        Self {
+            schedule,
+            current: EpochSecret::generate(epoch),
+            next: EpochSecret::generate(epoch + 1),
+            previous: None,
+            erased: false,
+        }
+    }
+
+    /// The rotation schedule
+    pub fn schedule(&self) -> EpochSchedule {
+        self.schedule
+    }
+
+    /// Current epoch
+    pub fn epoch(&self) -> u64 {
+        self.current.epoch
+    }
+
+    /// Public key of the current epoch
+    pub fn current_public_key(&self) -> &PublicKey {
+        &self.current.key_pair.public_key
+    }
+
+    /// Public keys of the current and the next epoch
+    pub fn published(&self) -> Vec<EpochKey> {
+        vec![self.current.public(), self.next.public()]
+    }
+
+    /// Secret keys packets may be encrypted to, current epoch first
+    pub fn secret_keys(&self) -> impl Iterator<Item = &SecretKey> {
+        let current = (!self.erased).then_some(&self.current.key_pair.secret_key);
+        current
+            .into_iter()
+            .chain(self.previous.as_ref().map(|previous| &previous.key_pair.secret_key))
+    }
+
+    /// Whether the previous epoch's key is still accepted
+    pub fn in_grace(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// Bring the keys up to date at `now`
+    ///
+    /// Rotates if a new epoch has begun, returning its number, and erases
+    /// the previous secret key once its grace window is over. A node that
+    /// slept through whole epochs skips them; their keys were never used.
+    pub fn advance(&mut self, now: SystemTime) -> Option<u64> {
+        if self.erased {
+            return None;
+        }
+        let epoch = self.schedule.epoch_at(now);
+        let mut rotated = None;
+
+        if epoch > self.current.epoch {
+            let next = std::mem::replace(&mut self.next, EpochSecret::generate(epoch + 1));
+            let current = if next.epoch == epoch { next } else { EpochSecret::generate(epoch) };
+            self.previous = Some(std::mem::replace(&mut self.current, current));
+            rotated = Some(epoch);
+        }
+
+        let grace_over = now >= self.schedule.start(self.current.epoch) + self.schedule.grace;
+        let stale = self.previous.as_ref().is_some_and(|previous| previous.epoch + 1 != self.current.epoch);
+        if grace_over || stale {
+            // Dropping the key pair zeroes its secret key
+            self.previous = None;
+        }
+
+        rotated
+    }
+
+    /// Erase every secret key, after which no packet can be opened and no
+    /// rotation happens
+    pub fn erase(&mut self) {
+        self.previous = None;
+        self.current.key_pair.secret_key.zeroize();
+        self.next.key_pair.secret_key.zeroize();
+        self.erased = true;
+    }
+
+    /// Time from `now` until `advance` next has work to do
+    pub fn next_change(&self, now: SystemTime) -> Duration {
+        let rotation = self.schedule.start(self.current.epoch + 1);
+        let due = match &self.previous {
+            Some(_) => rotation.min(self.schedule.start(self.current.epoch) + self.schedule.grace),
+            None => rotation,
+        };
+        due.duration_since(now).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{LayerOutcome, OutfoxPacket};
+
+    fn schedule() -> EpochSchedule {
+        EpochSchedule::new(Duration::from_secs(100), Duration::from_secs(10))
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// Try every held key on a single-hop packet, returning the message if one opens it
+    fn open(keys: &EpochKeys, packet: &OutfoxPacket) -> Option<Vec<u8>> {
+        keys.secret_keys().find_map(|secret_key| {
+            match packet.clone().process_layer(secret_key).ok()?.outcome {
+                LayerOutcome::Final { message } => Some(message),
+                LayerOutcome::Forward { .. } => None,
+            }
+        })
+    }
+
+    #[test]
+    fn test_epoch_numbering() {
+        let schedule = schedule();
+        assert_eq!(schedule.epoch_at(at(0)), 0);
+        assert_eq!(schedule.epoch_at(at(99)), 0);
+        assert_eq!(schedule.epoch_at(at(250)), 2);
+        assert_eq!(schedule.start(2), at(200));
+    }
+
+    #[test]
+    fn test_rotation_grace_and_erasure() {
+        let mut keys = EpochKeys::new(schedule(), at(1050));
+        assert_eq!(keys.epoch(), 10);
+        assert_eq!(keys.next_change(at(1050)), Duration::from_secs(50));
+
+        // Senders can already encrypt to the published next key
+        let published = keys.published();
+        assert_eq!(published.iter().map(|k| k.epoch).collect::<Vec<_>>(), vec![10, 11]);
+        let old_key = PublicKey::from_bytes(&published[0].public_key_bytes).unwrap();
+        let next_key = PublicKey::from_bytes(&published[1].public_key_bytes).unwrap();
+        let in_flight = OutfoxPacket::new(b"old", &[old_key]).unwrap();
+        let early = OutfoxPacket::new(b"next", &[next_key]).unwrap();
+
+        assert_eq!(keys.advance(at(1099)), None);
+        assert_eq!(keys.advance(at(1100)), Some(11));
+        assert_eq!(keys.current_public_key().as_bytes(), &published[1].public_key_bytes[..]);
+        assert_eq!(open(&keys, &early), Some(b"next".to_vec()));
+
+        // The old key works until the grace window closes
+        assert!(keys.in_grace());
+        assert_eq!(open(&keys, &in_flight), Some(b"old".to_vec()));
+        assert_eq!(keys.next_change(at(1105)), Duration::from_secs(5));
+
+        assert_eq!(keys.advance(at(1110)), None);
+        assert!(!keys.in_grace());
+        assert_eq!(keys.secret_keys().count(), 1);
+        assert_eq!(open(&keys, &in_flight), None);
+    }
+
+    #[test]
+    fn test_skipped_epochs_leave_no_previous_key() {
+        let mut keys = EpochKeys::new(schedule(), at(1000));
+
+        assert_eq!(keys.advance(at(1305)), Some(13));
+        assert!(!keys.in_grace());
+        assert_eq!(keys.published()[1].epoch, 14);
+    }
+}
@@ -0,0 +1,312 @@
+//! Loop-based active attack detection
+//!
+//! Loopix clients and mixes send loop messages that should come back to
+//! them. An adversary running an n-1 or blocking attack has to delay or
+//! drop traffic, and loops are indistinguishable from the rest, so losses
+//! show up as loops that fail to return. The monitor tracks every loop,
+//! measures the loss rate over a sliding window, and raises an alert when
+//! it passes a threshold. Blame goes only to mixes whose own loops are lost
+//! markedly more often than the loops that avoid them, so honest mixes
+//! sharing paths with an attacker are not punished for its drops.
+
+use crate::routing::ReputationSystem;
+use parking_lot::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Length of the identifier that opens every loop payload
+pub const LOOP_ID_SIZE: usize = 16;
+
+/// Loop monitoring parameters
+#[derive(Clone, Debug)]
+pub struct LoopMonitorConfig {
+    /// How long a loop may take to come back before it counts as lost
+    pub return_window: Duration,
+
+    /// Period over which the loss rate is measured
+    pub window: Duration,
+
+    /// Loss rate above which an alert is raised
+    pub loss_threshold: f64,
+
+    /// Resolved loops needed in the window before any alert
+    pub min_samples: usize,
+}
+
+impl Default for LoopMonitorConfig {
+    fn default() -> Self {
+        Self {
+            return_window: Duration::from_secs(30),
+            window: Duration::from_secs(600),
+            loss_threshold: 0.2,
+            min_samples: 10,
+        }
+    }
+}
+
+/// Suspected active attack
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopAlert {
+    /// Fraction of the window's resolved loops that were lost
+    pub loss_rate: f64,
+
+    /// Loops resolved in the window, returned or lost
+    pub resolved: usize,
+
+    /// Loops lost in the window
+    pub lost: usize,
+
+    /// Mixes whose loops were lost more often than the others by over the
+    /// threshold, with the loss rate of loops through each, most suspicious first
+    pub suspects: Vec<([u8; 32], f64)>,
+}
+
+/// A loop on its way back
+struct PendingLoop {
+    /// When the loop counts as lost
+    due: Instant,
+
+    /// Mixes the loop was routed through
+    path: Vec<[u8; 32]>,
+}
+
+/// A returned or lost loop inside the sliding window
+struct Outcome {
+    resolved: Instant,
+
+    /// Mixes the loop was routed through
+    path: Vec<[u8; 32]>,
+
+    /// Whether the loop failed to come back in time
+    lost: bool,
+
+    /// Whether this loss was already reported to the reputation system
+    attributed: bool,
+}
+
+/// Tracks loops and the loss rate over a sliding window
+pub struct LoopMonitor {
+    config: LoopMonitorConfig,
+
+    /// Address loops return to; never a suspect
+    own_address: [u8; 32],
+
+    pending: HashMap<[u8; LOOP_ID_SIZE], PendingLoop>,
+    outcomes: VecDeque<Outcome>,
+
+    /// Where blame for lost loops is recorded, if anywhere
+    reputation: Option<Arc<RwLock<ReputationSystem>>>,
+}
+
+impl LoopMonitor {
+    /// Create a monitor for loops returning to `own_address`
+    pub fn new(config: LoopMonitorConfig, own_address: [u8; 32]) -> Self {
+        Self {
+            config,
+            own_address,
+            pending: HashMap::new(),
+            outcomes: VecDeque::new(),
+            reputation: None,
+        }
+    }
+
+    /// Change the monitoring parameters; loops already sent keep their deadline
+    pub fn set_config(&mut self, config: LoopMonitorConfig) {
+        self.config = config;
+    }
+
+    /// Report suspected mixes to `reputation` when an alert is raised
+    pub fn set_reputation(&mut self, reputation: Arc<RwLock<ReputationSystem>>) {
+        self.reputation = Some(reputation);
+    }
+
+    /// Record a loop sent at `now` over `path`
+    pub fn record_sent(&mut self, loop_id: [u8; LOOP_ID_SIZE], path: Vec<[u8; 32]>, now: Instant) {
+        let due = now + self.config.return_window;
+        self.pending.insert(loop_id, PendingLoop { due, path });
+    }
+
+    /// Record a loop arriving back at `now`, returning whether it was pending
+    ///
+    /// A loop arriving after its deadline was already counted as lost and
+    /// is ignored.
+    pub fn record_returned(&mut self, loop_id: &[u8; LOOP_ID_SIZE], now: Instant) -> bool {
+        let Some(pending) = self.pending.remove(loop_id) else {
+            return false;
+        };
+        self.outcomes.push_back(Outcome { resolved: now, path: pending.path, lost: false, attributed: false });
+        true
+    }
+
+    /// Mark overdue loops lost and evaluate the window at `now`
+    ///
+    /// Returns the number of loops newly lost, and an alert if the loss rate
+    /// is above the threshold. On an alert, every loss not yet reported
+    /// counts as a failure of each suspect on its path.
+    ///
+    /// A mix is a suspect when the loss rate of loops through it exceeds
+    /// that of loops avoiding it by more than the threshold. A mix on every
+    /// loop cannot be told apart from the rest of its paths and is never one.
+    pub fn check(&mut self, now: Instant) -> (usize, Option<LoopAlert>) {
+        let mut newly_lost = 0;
+        let outcomes = &mut self.outcomes;
+        self.pending.retain(|_, pending| {
+            if now < pending.due {
+                return true;
+            }
+            outcomes.push_back(Outcome {
+                resolved: pending.due,
+                path: std::mem::take(&mut pending.path),
+                lost: true,
+                attributed: false,
+            });
+            newly_lost += 1;
+            false
+        });
+
+        while let Some(oldest) = self.outcomes.front() {
+            if now.duration_since(oldest.resolved) <= self.config.window {
+                break;
+            }
+            self.outcomes.pop_front();
+        }
+
+        let resolved = self.outcomes.len();
+        let lost = self.outcomes.iter().filter(|o| o.lost).count();
+        if resolved < self.config.min_samples.max(1) {
+            return (newly_lost, None);
+        }
+        let loss_rate = lost as f64 / resolved as f64;
+        if loss_rate <= self.config.loss_threshold {
+            return (newly_lost, None);
+        }
+
+        // Loops resolved and lost through each mix
+        let mut through: HashMap<[u8; 32], (usize, usize)> = HashMap::new();
+        for outcome in &self.outcomes {
+            for mix in outcome.path.iter().filter(|&&mix| mix != self.own_address) {
+                let (mix_resolved, mix_lost) = through.entry(*mix).or_default();
+                *mix_resolved += 1;
+                *mix_lost += usize::from(outcome.lost);
+            }
+        }
+
+        let mut suspects: Vec<([u8; 32], f64)> = through
+            .into_iter()
+            .filter(|&(_, (mix_resolved, _))| mix_resolved < resolved)
+            .filter_map(|(mix, (mix_resolved, mix_lost))| {
+                let rate = mix_lost as f64 / mix_resolved as f64;
+                let others = (lost - mix_lost) as f64 / (resolved - mix_resolved) as f64;
+                (rate - others > self.config.loss_threshold).then_some((mix, rate))
+            })
+            .collect();
+        suspects.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        if let Some(reputation) = &self.reputation {
+            let mut reputation = reputation.write();
+            for outcome in self.outcomes.iter_mut().filter(|o| o.lost && !o.attributed) {
+                outcome.attributed = true;
+                for (mix, _) in suspects.iter().filter(|(mix, _)| outcome.path.contains(mix)) {
+                    reputation.record_failure(mix);
+                }
+            }
+        }
+
+        (newly_lost, Some(LoopAlert { loss_rate, resolved, lost, suspects }))
+    }
+
+    /// Loops still on their way back
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: [u8; 32] = [0xAA; 32];
+
+    fn monitor() -> LoopMonitor {
+        let config = LoopMonitorConfig {
+            return_window: Duration::from_secs(10),
+            window: Duration::from_secs(100),
+            loss_threshold: 0.2,
+            min_samples: 10,
+        };
+        LoopMonitor::new(config, OWN)
+    }
+
+    fn id(n: u8) -> [u8; LOOP_ID_SIZE] {
+        [n; LOOP_ID_SIZE]
+    }
+
+    #[test]
+    fn test_returned_loops_raise_no_alert() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+
+        for n in 0..20 {
+            monitor.record_sent(id(n), vec![[1; 32], [2; 32], OWN], start);
+            assert!(monitor.record_returned(&id(n), start + Duration::from_secs(1)));
+        }
+
+        assert_eq!(monitor.check(start + Duration::from_secs(20)), (0, None));
+    }
+
+    #[test]
+    fn test_blocked_mix_blamed_for_lost_loops() {
+        let reputation = Arc::new(RwLock::new(ReputationSystem::new()));
+        for mix in [[1u8; 32], [2; 32], [3; 32]] {
+            reputation.write().init_node(mix, 1.0);
+        }
+        let mut monitor = monitor();
+        monitor.set_reputation(Arc::clone(&reputation));
+        let start = Instant::now();
+
+        // Mix 3 drops every loop routed through it
+        for n in 0..20 {
+            let middle = if n % 2 == 0 { [2; 32] } else { [3; 32] };
+            monitor.record_sent(id(n), vec![[1; 32], middle, OWN], start);
+            if n % 2 == 0 {
+                monitor.record_returned(&id(n), start + Duration::from_secs(1));
+            }
+        }
+
+        let (newly_lost, alert) = monitor.check(start + Duration::from_secs(11));
+        let alert = alert.unwrap();
+        assert_eq!(newly_lost, 10);
+        assert_eq!((alert.resolved, alert.lost), (20, 10));
+        // The entry mix carried every loop, lost or not, so only mix 3 stands out
+        assert_eq!(alert.suspects, vec![([3; 32], 1.0)]);
+
+        let failures = |mix: [u8; 32]| reputation.read().get_reputation(&mix).unwrap().failures;
+        assert_eq!(failures([3; 32]), 10);
+        assert_eq!(failures([1; 32]), 0);
+        assert_eq!(failures([2; 32]), 0);
+
+        // Losses are only blamed once
+        monitor.check(start + Duration::from_secs(12));
+        assert_eq!(failures([3; 32]), 10);
+    }
+
+    #[test]
+    fn test_old_losses_leave_the_window() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+
+        for n in 0..10 {
+            monitor.record_sent(id(n), vec![[1; 32], OWN], start);
+        }
+        assert!(monitor.check(start + Duration::from_secs(10)).1.is_some());
+
+        // Long after the losses, a run of returned loops is all the window sees
+        let later = start + Duration::from_secs(200);
+        for n in 10..20 {
+            monitor.record_sent(id(n), vec![[1; 32], OWN], later);
+            monitor.record_returned(&id(n), later);
+        }
+        assert_eq!(monitor.check(later), (0, None));
+    }
+}
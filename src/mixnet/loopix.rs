@@ -1,138 +1,507 @@
 //! Loopix Cover Traffic System
 //! 
 //! Implements the Loopix mixing strategy (USENIX Security 2017)
-//! with three types of cover traffic to hide communication patterns
+//! with three types of cover traffic to hide communication patterns.
+//! Clients send loops, drops and real messages; mixes send loops of their
+//! own to detect attacks on themselves.
 
 use tokio::time::Duration;
+use parking_lot::{Mutex, RwLock};
+use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_distr::{Distribution, Exp};
-use crate::protocols::sphinx::SphinxBuilder;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use crate::config::DropPolicy;
+use crate::error::{AetherError, PacketError, Result};
+use crate::mixnet::loop_monitor::{LoopAlert, LoopMonitor, LoopMonitorConfig, LOOP_ID_SIZE};
+use crate::mixnet::queue::PacketQueue;
+use crate::protocols::sphinx::{SphinxBuilder, SphinxPacket, MAX_HOPS, MAX_MESSAGE_SIZE};
+use crate::routing::ReputationSystem;
+
+/// Most application messages waiting for a sending slot
+const OUTBOX_CAPACITY: usize = 10_000;
+
+/// Layered view of the network that cover traffic is routed through
+#[derive(Clone, Debug, Default)]
+pub struct LoopixTopology {
+    /// Sphinx public keys of the mixes in each layer, entry layer first
+    pub layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl LoopixTopology {
+    /// Create a topology from its layers, entry layer first
+    ///
+    /// Fails if a path through every layer plus its final hop (a loop's
+    /// sender or a message's recipient) would not fit in a Sphinx header.
+    pub fn new(layers: Vec<Vec<[u8; 32]>>) -> Result<Self> {
+        let topology = Self { layers };
+        topology.check_depth()?;
+        Ok(topology)
+    }
+
+    /// Fail if paths through this topology are too long for Sphinx
+    fn check_depth(&self) -> Result<()> {
+        if self.layers.len() + 1 > MAX_HOPS {
+            return Err(AetherError::Config(format!(
+                "Loopix topology has {} layers, at most {} fit in a Sphinx path",
+                self.layers.len(),
+                MAX_HOPS - 1
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pick one random mix from every layer, in layer order
+    ///
+    /// Returns `None` if there are no layers or some layer is empty.
+    pub fn random_path<R: Rng>(&self, rng: &mut R) -> Option<Vec<[u8; 32]>> {
+        if self.layers.is_empty() {
+            return None;
+        }
+        self.layers.iter().map(|layer| layer.choose(rng).copied()).collect()
+    }
+
+    /// Pick a loop for the mix `own`: one random mix from every other layer,
+    /// in layer order after `own`'s layer, then `own` itself
+    ///
+    /// Returns `None` if `own` is not in the topology, it is the only
+    /// layer, or some other layer is empty.
+    pub fn mix_loop_path<R: Rng>(&self, own: [u8; 32], rng: &mut R) -> Option<Vec<[u8; 32]>> {
+        let own_layer = self.layers.iter().position(|layer| layer.contains(&own))?;
+        let layers = self.layers.len();
+        if layers < 2 {
+            return None;
+        }
+
+        let mut path = (1..layers)
+            .map(|step| self.layers[(own_layer + step) % layers].choose(rng).copied())
+            .collect::<Option<Vec<_>>>()?;
+        path.push(own);
+        Some(path)
+    }
+}
+
+/// Poisson rates of the Loopix traffic streams, in messages per second
+///
+/// A rate of zero pauses its stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopixRates {
+    /// λ_L: client loop messages
+    pub loop_rate: f64,
+
+    /// λ_D: client drop messages
+    pub drop_rate: f64,
+
+    /// λ_P: client sending slots for real messages
+    pub payload_rate: f64,
+
+    /// λ_M: mix loop messages
+    pub mix_loop_rate: f64,
+}
+
+impl Default for LoopixRates {
+    fn default() -> Self {
+        Self {
+            loop_rate: 0.5,  // λ_L = 0.5 messages/sec (from paper)
+            drop_rate: 0.3,  // λ_D = 0.3 messages/sec
+            payload_rate: 1.0,  // λ_P = 1 message/sec
+            mix_loop_rate: 0.5,  // λ_M = 0.5 messages/sec
+        }
+    }
+}
+
+/// Which side of the network cover traffic is generated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopixRole {
+    /// Loops, drops and real messages from a client
+    Client,
+
+    /// Loops from a mix through the other layers back to itself
+    Mix,
+}
+
+/// One Poisson traffic stream
+#[derive(Clone, Copy, Debug)]
+enum Stream {
+    Loop,
+    Drop,
+    Payload,
+    MixLoop,
+}
+
+impl Stream {
+    fn rate(self, rates: &LoopixRates) -> f64 {
+        match self {
+            Stream::Loop => rates.loop_rate,
+            Stream::Drop => rates.drop_rate,
+            Stream::Payload => rates.payload_rate,
+            Stream::MixLoop => rates.mix_loop_rate,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stream::Loop => "loop",
+            Stream::Drop => "drop",
+            Stream::Payload => "payload",
+            Stream::MixLoop => "mix loop",
+        }
+    }
+
+    /// Send this stream's next message
+    fn emit(self, shared: &Shared) -> Result<()> {
+        match self {
+            Stream::Loop => {
+                shared.send_loop(shared.client_loop_path())?;
+                shared.check_loops();
+            }
+            Stream::MixLoop => {
+                let path = shared.topology.read().mix_loop_path(shared.own_address, &mut rand::thread_rng());
+                shared.send_loop(path)?;
+                shared.check_loops();
+            }
+            Stream::Drop => shared.send_drop()?,
+            Stream::Payload => shared.send_payload_slot()?,
+        }
+        Ok(())
+    }
+}
+
+/// Where cover packets are handed over for transmission
+pub trait PacketSink: Send + Sync {
+    /// Queue `packet` for the mix whose Sphinx key is `first_hop`
+    fn send(&self, first_hop: [u8; 32], packet: SphinxPacket) -> Result<()>;
+}
+
+impl PacketSink for mpsc::UnboundedSender<([u8; 32], SphinxPacket)> {
+    fn send(&self, first_hop: [u8; 32], packet: SphinxPacket) -> Result<()> {
+        mpsc::UnboundedSender::send(self, (first_hop, packet))
+            .map_err(|_| AetherError::Network("Packet sink closed".to_string()))
+    }
+}
+
+/// A packet sink and the receiving end of the channel it queues packets on
+pub type SinkChannel = (Arc<dyn PacketSink>, mpsc::UnboundedReceiver<([u8; 32], SphinxPacket)>);
+
+/// A sink that queues packets on a channel, and the channel's receiving end
+pub fn channel_sink() -> SinkChannel {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (Arc::new(sender), receiver)
+}
+
+/// State shared between the cover traffic workers and their owner
+struct Shared {
+    own_address: [u8; 32],
+    topology: RwLock<LoopixTopology>,
+    sink: Arc<dyn PacketSink>,
+    monitor: Mutex<LoopMonitor>,
+    /// Application messages and their recipients' Sphinx keys
+    outbox: PacketQueue<([u8; 32], Vec<u8>)>,
+    stats: Mutex<CoverTrafficStats>,
+}
+
+impl Shared {
+    /// A random path through every layer and back to us
+    fn client_loop_path(&self) -> Option<Vec<[u8; 32]>> {
+        let mut path = self.topology.read().random_path(&mut rand::thread_rng())?;
+        path.push(self.own_address);
+        Some(path)
+    }
+
+    /// Send one loop message over `path`, which ends at our own address
+    fn send_loop(&self, path: Option<Vec<[u8; 32]>>) -> Result<()> {
+        let Some(path) = path else {
+            tracing::debug!("No complete path through the topology, skipping loop");
+            return Ok(());
+        };
+
+        let mut loop_id = [0u8; LOOP_ID_SIZE];
+        rand::thread_rng().fill(&mut loop_id);
+        let mut message = LoopixCoverTraffic::generate_dummy_payload();
+        message[..LOOP_ID_SIZE].copy_from_slice(&loop_id);
+
+        let packet = SphinxBuilder::new(path.clone(), message)
+            .build()
+            .map_err(|e| AetherError::Crypto(format!("Failed to create loop packet: {}", e)))?;
+        self.sink.send(path[0], packet)?;
+        tracing::debug!("🔄 Sent loop cover traffic");
+
+        self.monitor.lock().record_sent(loop_id, path, Instant::now());
+        let mut stats = self.stats.lock();
+        stats.loop_messages += 1;
+        stats.update_ratio();
+        Ok(())
+    }
+
+    /// Send one drop message, which the last mix on its path discards
+    fn send_drop(&self) -> Result<()> {
+        let Some(path) = self.topology.read().random_path(&mut rand::thread_rng()) else {
+            tracing::debug!("No complete path through the topology, skipping drop");
+            return Ok(());
+        };
+
+        let packet = SphinxBuilder::new(path.clone(), LoopixCoverTraffic::generate_dummy_payload())
+            .build()
+            .map_err(|e| AetherError::Crypto(format!("Failed to create drop packet: {}", e)))?;
+        self.sink.send(path[0], packet)?;
+        tracing::debug!("🗑️  Sent drop cover traffic");
+
+        let mut stats = self.stats.lock();
+        stats.drop_messages += 1;
+        stats.update_ratio();
+        Ok(())
+    }
+
+    /// Fill one sending slot: the oldest queued message, or a drop message
+    /// if there is none, so that idle slots look the same as busy ones
+    fn send_payload_slot(&self) -> Result<()> {
+        let Some(mut path) = self.topology.read().random_path(&mut rand::thread_rng()) else {
+            tracing::debug!("No complete path through the topology, skipping slot");
+            return Ok(());
+        };
+        let Some((recipient, message)) = self.outbox.pop() else {
+            return self.send_drop();
+        };
+        path.push(recipient);
+
+        // A message is only dequeued for good once its packet is handed over
+        let sent = SphinxBuilder::new(path.clone(), message.clone())
+            .build()
+            .map_err(|e| AetherError::Crypto(format!("Failed to create message packet: {}", e)))
+            .and_then(|packet| self.sink.send(path[0], packet));
+        if let Err(e) = sent {
+            self.outbox.requeue((recipient, message));
+            return Err(e);
+        }
+
+        let mut stats = self.stats.lock();
+        stats.real_messages += 1;
+        stats.update_ratio();
+        Ok(())
+    }
+
+    /// Count overdue loops as lost and check the loss rate for an attack
+    fn check_loops(&self) -> Option<LoopAlert> {
+        let (newly_lost, alert) = self.monitor.lock().check(Instant::now());
+
+        let mut stats = self.stats.lock();
+        stats.loops_lost += newly_lost as u64;
+        if let Some(alert) = &alert {
+            stats.alerts_raised += 1;
+            tracing::warn!(
+                "🚨 Possible active attack: {:.0}% of {} loops lost, {} mixes suspected",
+                alert.loss_rate * 100.0,
+                alert.resolved,
+                alert.suspects.len()
+            );
+        }
+        alert
+    }
+}
 
 /// Loopix cover traffic generator
 pub struct LoopixCoverTraffic {
-    /// Rate of loop messages (sent to yourself)
-    loop_rate: f64,
+    /// Which streams run
+    role: LoopixRole,
     
-    /// Rate of drop messages (sent to nowhere)
-    drop_rate: f64,
+    /// Current rates; workers pick up changes at once
+    rates: watch::Sender<LoopixRates>,
     
-    /// Your own address (for loop traffic)
-    own_address: [u8; 32],
+    /// Topology, sink, pending loops and statistics
+    shared: Arc<Shared>,
+    
+    /// Cancels the running workers
+    cancel: CancellationToken,
     
-    /// Running state
-    running: bool,
+    /// Running workers
+    workers: Vec<JoinHandle<()>>,
 }
 
 impl LoopixCoverTraffic {
-    /// Create new Loopix cover traffic generator
-    pub fn new(own_address: [u8; 32]) -> Self {
+    /// Create new Loopix cover traffic generator for a client
+    ///
+    /// `own_address` is our Sphinx public key, where loops return to.
+    pub fn new(own_address: [u8; 32], topology: LoopixTopology, sink: Arc<dyn PacketSink>) -> Self {
+        Self::with_role(LoopixRole::Client, own_address, topology, sink)
+    }
+
+    /// Create a generator of loop traffic for the mix with Sphinx key `own_address`
+    pub fn for_mix(own_address: [u8; 32], topology: LoopixTopology, sink: Arc<dyn PacketSink>) -> Self {
+        Self::with_role(LoopixRole::Mix, own_address, topology, sink)
+    }
+
+    fn with_role(role: LoopixRole, own_address: [u8; 32], topology: LoopixTopology, sink: Arc<dyn PacketSink>) -> Self {
         Self {
-            loop_rate: 0.5,  // λ_L = 0.5 messages/sec (from paper)
-            drop_rate: 0.3,  // λ_D = 0.3 messages/sec
-            own_address,
-            running: false,
+            role,
+            rates: watch::Sender::new(LoopixRates::default()),
+            shared: Arc::new(Shared {
+                own_address,
+                topology: RwLock::new(topology),
+                sink,
+                monitor: Mutex::new(LoopMonitor::new(LoopMonitorConfig::default(), own_address)),
+                outbox: PacketQueue::bounded(OUTBOX_CAPACITY, DropPolicy::DropNewest),
+                stats: Mutex::new(CoverTrafficStats::default()),
+            }),
+            cancel: CancellationToken::new(),
+            workers: Vec::new(),
         }
     }
 
-    /// Start generating cover traffic
-    pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.running = true;
-        tracing::info!("🎭 Starting Loopix cover traffic");
-        tracing::info!("   Loop rate: {} msg/sec", self.loop_rate);
-        tracing::info!("   Drop rate: {} msg/sec", self.drop_rate);
-
-        // Spawn loop traffic generator
-        let loop_rate = self.loop_rate;
-        let own_addr = self.own_address;
-        tokio::task::spawn(async move {
-            Self::loop_traffic_worker(loop_rate, own_addr).await;
-        });
+    /// Current rates
+    pub fn rates(&self) -> LoopixRates {
+        *self.rates.borrow()
+    }
 
-        // Spawn drop traffic generator  
-        let drop_rate = self.drop_rate;
-        tokio::task::spawn(async move {
-            Self::drop_traffic_worker(drop_rate).await;
-        });
+    /// Change the rates, including while running
+    pub fn set_rates(&self, rates: LoopixRates) {
+        self.rates.send_replace(rates);
+    }
 
+    /// Replace the topology that new cover messages are routed through
+    ///
+    /// A topology too deep for Sphinx paths is refused and the current one kept.
+    pub fn set_topology(&self, topology: LoopixTopology) -> Result<()> {
+        topology.check_depth()?;
+        *self.shared.topology.write() = topology;
         Ok(())
     }
 
-    /// Worker for loop traffic (messages to yourself)
-    async fn loop_traffic_worker(rate: f64, own_address: [u8; 32]) {
-        let exp_dist = Exp::new(rate).unwrap();
+    /// Change how returning loops are judged
+    pub fn set_monitor_config(&self, config: LoopMonitorConfig) {
+        self.shared.monitor.lock().set_config(config);
+    }
 
-        loop {
-            // Create RNG inside loop to avoid Send issues
-            let delay_secs = {
-                let mut rng = rand::thread_rng();
-                exp_dist.sample(&mut rng)
-            };
-            
-            tokio::time::sleep(Duration::from_secs_f64(delay_secs)).await;
+    /// Blame the mixes on failing loop paths in `reputation` when an attack is suspected
+    pub fn set_reputation(&self, reputation: Arc<RwLock<ReputationSystem>>) {
+        self.shared.monitor.lock().set_reputation(reputation);
+    }
 
-            // Create loop message
-            let path = vec![
-                own_address,
-                Self::random_relay(),
-                Self::random_relay(),
-                own_address, // Back to us
-            ];
-
-            let message = Self::generate_dummy_payload();
-            
-            match SphinxBuilder::new(path, message).build() {
-                Ok(_packet) => {
-                    tracing::debug!("🔄 Generated loop cover traffic");
-                    // TODO: Send packet into network
-                }
-                Err(e) => {
-                    tracing::error!("Failed to create loop packet: {}", e);
-                }
+    /// Current cover traffic statistics
+    pub fn stats(&self) -> CoverTrafficStats {
+        self.shared.stats.lock().clone()
+    }
+
+    /// Whether the workers are running
+    pub fn is_running(&self) -> bool {
+        !self.workers.is_empty()
+    }
+
+    /// Start generating cover traffic
+    pub async fn start(&mut self) -> Result<()> {
+        if self.is_running() {
+            return Err(AetherError::InvalidState("Loopix cover traffic already running".to_string()));
+        }
+
+        let rates = self.rates();
+        let streams: &[Stream] = match self.role {
+            LoopixRole::Client => {
+                tracing::info!("🎭 Starting Loopix cover traffic");
+                tracing::info!("   Loop rate: {} msg/sec", rates.loop_rate);
+                tracing::info!("   Drop rate: {} msg/sec", rates.drop_rate);
+                tracing::info!("   Payload rate: {} msg/sec", rates.payload_rate);
+                &[Stream::Loop, Stream::Drop, Stream::Payload]
+            }
+            LoopixRole::Mix => {
+                tracing::info!("🎭 Starting Loopix mix loop traffic");
+                tracing::info!("   Mix loop rate: {} msg/sec", rates.mix_loop_rate);
+                &[Stream::MixLoop]
             }
+        };
+
+        // A cancelled token stays cancelled, so every start gets a fresh one
+        self.cancel = CancellationToken::new();
+        for &stream in streams {
+            self.workers.push(tokio::task::spawn(Self::poisson_worker(
+                stream,
+                Arc::clone(&self.shared),
+                self.rates.subscribe(),
+                self.cancel.clone(),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Queue an application message for `recipient` (a Sphinx public key)
+    ///
+    /// It leaves in the next sending slot of the λ_P Poisson process, in
+    /// place of the drop message that slot would otherwise carry.
+    pub fn send_message(&self, recipient: [u8; 32], message: Vec<u8>) -> Result<()> {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(AetherError::Packet(PacketError::Malformed(format!(
+                "Message of {} bytes exceeds {} bytes",
+                message.len(),
+                MAX_MESSAGE_SIZE
+            ))));
         }
+        if !self.shared.outbox.push((recipient, message)) {
+            return Err(AetherError::InvalidState("Message queue is full".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Messages waiting for a sending slot
+    pub fn queued_messages(&self) -> usize {
+        self.shared.outbox.len()
     }
 
-    /// Worker for drop traffic (messages to nowhere)
-    async fn drop_traffic_worker(rate: f64) {
-        let exp_dist = Exp::new(rate).unwrap();
+    /// Check a payload delivered to us, returning whether it was one of our loops
+    ///
+    /// Call this with every final payload our Sphinx processor yields.
+    pub fn loop_received(&self, payload: &[u8]) -> bool {
+        let Some(loop_id) = payload.get(..LOOP_ID_SIZE) else {
+            return false;
+        };
+        let loop_id: [u8; LOOP_ID_SIZE] = loop_id.try_into().expect("slice has loop id length");
+
+        if !self.shared.monitor.lock().record_returned(&loop_id, Instant::now()) {
+            return false;
+        }
+        self.shared.stats.lock().loops_returned += 1;
+        true
+    }
 
+    /// Worker sending one stream's messages with exponential gaps
+    async fn poisson_worker(
+        stream: Stream,
+        shared: Arc<Shared>,
+        mut rates: watch::Receiver<LoopixRates>,
+        cancel: CancellationToken,
+    ) {
         loop {
+            let rate = stream.rate(&rates.borrow_and_update());
+
             // Create RNG inside loop to avoid Send issues
-            let delay_secs = {
+            let gap = (rate > 0.0).then(|| {
                 let mut rng = rand::thread_rng();
-                exp_dist.sample(&mut rng)
-            };
-            
-            tokio::time::sleep(Duration::from_secs_f64(delay_secs)).await;
-
-            // Create drop message (goes to a fake destination that drops it)
-            let path = vec![
-                Self::random_relay(),
-                Self::random_relay(),
-                Self::random_relay(),
-                [0xFFu8; 32], // Special "drop" address
-            ];
-
-            let message = Self::generate_dummy_payload();
-            
-            match SphinxBuilder::new(path, message).build() {
-                Ok(_packet) => {
-                    tracing::debug!("🗑️  Generated drop cover traffic");
-                    // TODO: Send packet into network
-                }
-                Err(e) => {
-                    tracing::error!("Failed to create drop packet: {}", e);
+                Duration::from_secs_f64(Exp::new(rate).unwrap().sample(&mut rng))
+            });
+
+            tokio::select! {
+                _ = cancel.cancelled() => return,
+                changed = rates.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    // Gaps are memoryless, so drawing a new one at the new rate is fair
+                    continue;
                 }
+                _ = async {
+                    match gap {
+                        Some(gap) => tokio::time::sleep(gap).await,
+                        None => std::future::pending().await,
+                    }
+                } => {}
             }
-        }
-    }
 
-    /// Generate random relay address
-    fn random_relay() -> [u8; 32] {
-        let mut rng = rand::thread_rng();
-        let mut addr = [0u8; 32];
-        rng.fill(&mut addr);
-        addr
+            if let Err(e) = stream.emit(&shared) {
+                tracing::error!("Failed to send {} packet: {}", stream.name(), e);
+            }
+        }
     }
 
     /// Generate dummy payload (indistinguishable from real messages)
@@ -144,15 +513,27 @@ impl LoopixCoverTraffic {
         payload
     }
 
-    /// Stop cover traffic generation
-    pub fn stop(&mut self) {
-        self.running = false;
+    /// Stop cover traffic generation and wait for the workers to finish
+    pub async fn stop(&mut self) {
+        self.cancel.cancel();
+        for worker in self.workers.drain(..) {
+            if let Err(e) = worker.await {
+                tracing::error!("Cover traffic worker failed: {}", e);
+            }
+        }
         tracing::info!("⏹️  Stopped Loopix cover traffic");
     }
 }
 
+impl Drop for LoopixCoverTraffic {
+    fn drop(&mut self) {
+        // Workers must not outlive their owner
+        self.cancel.cancel();
+    }
+}
+
 /// Statistics for cover traffic
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CoverTrafficStats {
     /// Total loop messages sent
     pub loop_messages: u64,
@@ -162,6 +543,15 @@ pub struct CoverTrafficStats {
     
     /// Total real messages sent
     pub real_messages: u64,
+
+    /// Loop messages that came back
+    pub loops_returned: u64,
+
+    /// Loop messages that did not come back in time
+    pub loops_lost: u64,
+
+    /// Times the loop loss rate suggested an active attack
+    pub alerts_raised: u64,
     
     /// Average cover traffic ratio
     pub cover_ratio: f64,
@@ -181,8 +571,10 @@ impl CoverTrafficStats {
     /// Get human-readable stats
     pub fn summary(&self) -> String {
         format!(
-            "Loop: {}, Drop: {}, Real: {}, Cover Ratio: {:.1}%",
+            "Loop: {} ({} returned, {} lost), Drop: {}, Real: {}, Cover Ratio: {:.1}%",
             self.loop_messages,
+            self.loops_returned,
+            self.loops_lost,
             self.drop_messages,
             self.real_messages,
             self.cover_ratio * 100.0
@@ -193,13 +585,31 @@ impl CoverTrafficStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocols::sphinx::SphinxProcessor;
+
+    fn processors(n: usize) -> Vec<SphinxProcessor> {
+        let mut rng = rand::thread_rng();
+        (0..n).map(|_| SphinxProcessor::new(rng.gen())).collect()
+    }
+
+    /// Cover traffic for `client` over one single-mix layer per mix, with the sent packets
+    fn loopix_over(
+        client: &SphinxProcessor,
+        mixes: &[SphinxProcessor],
+    ) -> (LoopixCoverTraffic, mpsc::UnboundedReceiver<([u8; 32], SphinxPacket)>) {
+        let topology = LoopixTopology::new(mixes.iter().map(|m| vec![m.public_key()]).collect()).unwrap();
+        let (sink, sent) = channel_sink();
+        (LoopixCoverTraffic::new(client.public_key(), topology, sink), sent)
+    }
 
     #[test]
     fn test_loopix_creation() {
         let addr = [1u8; 32];
-        let loopix = LoopixCoverTraffic::new(addr);
-        assert_eq!(loopix.loop_rate, 0.5);
-        assert_eq!(loopix.drop_rate, 0.3);
+        let (sink, _sent) = channel_sink();
+        let loopix = LoopixCoverTraffic::new(addr, LoopixTopology::default(), sink);
+        assert_eq!(loopix.rates().loop_rate, 0.5);
+        assert_eq!(loopix.rates().drop_rate, 0.3);
+        assert!(!loopix.is_running());
     }
 
     #[test]
@@ -212,4 +622,174 @@ mod tests {
         
         assert!(stats.cover_ratio > 0.7); // 80/100 = 80% cover traffic
     }
+
+    #[test]
+    fn test_loop_returns_through_every_layer() {
+        let client = processors(1).remove(0);
+        let mixes = processors(3);
+        let (loopix, mut sent) = loopix_over(&client, &mixes);
+
+        loopix.shared.send_loop(loopix.shared.client_loop_path()).unwrap();
+        let (first_hop, mut packet) = sent.try_recv().unwrap();
+        assert_eq!(first_hop, mixes[0].public_key());
+
+        for mix in &mixes {
+            packet = mix.process(packet).unwrap().new_packet.unwrap();
+        }
+        let payload = client.process(packet).unwrap().final_payload.unwrap();
+
+        assert!(loopix.loop_received(&payload));
+        assert!(!loopix.loop_received(&payload));
+        let stats = loopix.stats();
+        assert_eq!((stats.loop_messages, stats.loops_returned, stats.loops_lost), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_five_layer_loop_fits_sphinx_path() {
+        let client = processors(1).remove(0);
+        let mixes = processors(5);
+        let (loopix, mut sent) = loopix_over(&client, &mixes);
+
+        loopix.shared.send_loop(loopix.shared.client_loop_path()).unwrap();
+        let (_, mut packet) = sent.try_recv().unwrap();
+        for mix in &mixes {
+            packet = mix.process(packet).unwrap().new_packet.unwrap();
+        }
+        let payload = client.process(packet).unwrap().final_payload.unwrap();
+        assert!(loopix.loop_received(&payload));
+
+        // A sixth layer leaves no room for the hop back to the client
+        let layers = processors(6).iter().map(|m| vec![m.public_key()]).collect();
+        assert!(matches!(LoopixTopology::new(layers), Err(AetherError::Config(_))));
+    }
+
+    #[test]
+    fn test_lost_loop_raises_alert_and_blames_path() {
+        let client = processors(1).remove(0);
+        let mixes = processors(3);
+        let (entry, honest, dropper) = (mixes[0].public_key(), mixes[1].public_key(), mixes[2].public_key());
+        let (loopix, mut sent) = loopix_over(&client, &mixes);
+        let reputation = Arc::new(RwLock::new(ReputationSystem::new()));
+        for mix in &mixes {
+            reputation.write().init_node(mix.public_key(), 1.0);
+        }
+        loopix.set_reputation(Arc::clone(&reputation));
+        loopix.set_monitor_config(LoopMonitorConfig {
+            return_window: Duration::ZERO,
+            min_samples: 1,
+            ..LoopMonitorConfig::default()
+        });
+
+        // One loop comes back through the honest mix, one is lost at the dropper
+        loopix.shared.send_loop(Some(vec![entry, honest, client.public_key()])).unwrap();
+        let (_, mut packet) = sent.try_recv().unwrap();
+        for mix in &mixes[..2] {
+            packet = mix.process(packet).unwrap().new_packet.unwrap();
+        }
+        assert!(loopix.loop_received(&client.process(packet).unwrap().final_payload.unwrap()));
+        loopix.shared.send_loop(Some(vec![entry, dropper, client.public_key()])).unwrap();
+        let alert = loopix.shared.check_loops().unwrap();
+
+        assert_eq!((alert.resolved, alert.lost), (2, 1));
+        assert_eq!(alert.suspects, vec![(dropper, 1.0)]);
+        let failures = |mix: [u8; 32]| reputation.read().get_reputation(&mix).unwrap().failures;
+        assert_eq!((failures(entry), failures(honest), failures(dropper)), (0, 0, 1));
+        let stats = loopix.stats();
+        assert_eq!((stats.loops_lost, stats.alerts_raised), (1, 1));
+    }
+
+    #[test]
+    fn test_empty_slot_sends_drop_in_place_of_message() {
+        let client = processors(1).remove(0);
+        let recipient = processors(1).remove(0);
+        let mixes = processors(3);
+        let (loopix, mut sent) = loopix_over(&client, &mixes);
+
+        loopix.send_message(recipient.public_key(), b"hello".to_vec()).unwrap();
+        loopix.shared.send_payload_slot().unwrap();
+        loopix.shared.send_payload_slot().unwrap();
+
+        // Both slots carry a packet of the same shape to an entry mix
+        let (first_hop, mut real) = sent.try_recv().unwrap();
+        let (drop_hop, drop) = sent.try_recv().unwrap();
+        assert_eq!((first_hop, drop_hop), (mixes[0].public_key(), mixes[0].public_key()));
+        assert_eq!(real.header.beta.len(), drop.header.beta.len());
+        assert_eq!(real.payload.len(), drop.payload.len());
+
+        for mix in &mixes {
+            real = mix.process(real).unwrap().new_packet.unwrap();
+        }
+        let payload = recipient.process(real).unwrap().final_payload.unwrap();
+        assert_eq!(&payload[..5], b"hello");
+
+        let stats = loopix.stats();
+        assert_eq!((stats.real_messages, stats.drop_messages), (1, 1));
+        assert_eq!(loopix.queued_messages(), 0);
+    }
+
+    #[test]
+    fn test_failed_slot_keeps_message_queued() {
+        let client = processors(1).remove(0);
+        let mixes = processors(3);
+        let (loopix, sent) = loopix_over(&client, &mixes);
+        drop(sent);
+
+        loopix.send_message([7u8; 32], b"hello".to_vec()).unwrap();
+        assert!(loopix.shared.send_payload_slot().is_err());
+        assert_eq!(loopix.queued_messages(), 1);
+        assert_eq!(loopix.stats().real_messages, 0);
+
+        // Too deep a topology is refused up front rather than on every slot
+        let deep = LoopixTopology { layers: vec![vec![[1u8; 32]]; MAX_HOPS] };
+        assert!(loopix.set_topology(deep).is_err());
+        assert_eq!(loopix.shared.topology.read().layers.len(), 3);
+    }
+
+    #[test]
+    fn test_no_cover_without_complete_topology() {
+        let client = processors(1).remove(0);
+        let (sink, mut sent) = channel_sink();
+        let topology = LoopixTopology::new(vec![vec![[2u8; 32]], vec![]]).unwrap();
+        let loopix = LoopixCoverTraffic::new(client.public_key(), topology, sink);
+
+        loopix.shared.send_loop(loopix.shared.client_loop_path()).unwrap();
+        loopix.shared.send_drop().unwrap();
+
+        assert!(sent.try_recv().is_err());
+        assert_eq!(loopix.stats().loop_messages + loopix.stats().drop_messages, 0);
+    }
+
+    #[test]
+    fn test_mix_loop_visits_other_layers_and_returns() {
+        let topology = LoopixTopology::new(vec![vec![[1; 32]], vec![[2; 32]], vec![[3; 32]]]).unwrap();
+        let mut rng = rand::thread_rng();
+
+        assert_eq!(topology.mix_loop_path([2; 32], &mut rng), Some(vec![[3; 32], [1; 32], [2; 32]]));
+        assert_eq!(topology.mix_loop_path([9; 32], &mut rng), None);
+    }
+
+    #[tokio::test]
+    async fn test_rates_change_while_running_and_stop_joins_workers() {
+        let client = processors(1).remove(0);
+        let mixes = processors(3);
+        let (mut loopix, mut sent) = loopix_over(&client, &mixes);
+        let paused = LoopixRates { loop_rate: 0.0, drop_rate: 0.0, payload_rate: 0.0, mix_loop_rate: 0.0 };
+        loopix.set_rates(paused);
+        loopix.start().await.unwrap();
+        assert!(loopix.start().await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(sent.try_recv().is_err());
+
+        // Raising a rate takes effect without a restart
+        loopix.set_rates(LoopixRates { drop_rate: 200.0, ..paused });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(sent.try_recv().is_ok());
+
+        loopix.stop().await;
+        assert!(!loopix.is_running());
+        while sent.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(sent.try_recv().is_err());
+    }
 }
@@ -2,16 +2,123 @@
 //! 
 //! Defines how packets are delayed and batched to resist traffic analysis.
 
+use crate::config::{AetherConfig, MixingConfig};
+use crate::error::{AetherError, Result};
 use std::time::Duration;
-use rand_distr::{Distribution, Exp};
+use rand_distr::{Binomial, Distribution, Exp};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of one flush round of a pool strategy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flush {
+    /// Packets released
+    pub released: usize,
+    
+    /// Packets any released packet could be confused with: everything in
+    /// the pool at flush time, including packets the pool keeps back
+    pub anonymity_set: usize,
+}
+
+impl Flush {
+    /// Release the whole pool
+    pub fn all(pool_size: usize) -> Self {
+        Self { released: pool_size, anonymity_set: pool_size }
+    }
+}
 
 /// Trait for mixing strategies
+///
+/// A strategy either delays each packet independently (`calculate_delay`) or
+/// pools packets and releases them in batches (`flush`), depending on
+/// `is_pool`.
 pub trait MixingStrategy: Send + Sync {
     /// Calculate the delay for a packet based on its size and current node load
     fn calculate_delay(&self, packet_size: usize, current_load: f64) -> Duration;
     
     /// Get the human-readable name of the mixing strategy
     fn name(&self) -> &str;
+    
+    /// Whether packets are pooled and released by `flush` instead of delayed
+    fn is_pool(&self) -> bool {
+        false
+    }
+    
+    /// Decide how many of the `pool_size` pooled packets to release,
+    /// `elapsed` after the previous flush
+    ///
+    /// Called whenever a packet joins the pool and on every node tick.
+    /// `None` holds the pool; `Some` is a flush round (possibly releasing
+    /// nothing) that restarts the timer. The node picks the released packets
+    /// uniformly at random from the pool.
+    fn flush(&self, _pool_size: usize, _elapsed: Duration) -> Option<Flush> {
+        None
+    }
+}
+
+/// Build the mixing strategy selected in the configuration
+pub fn strategy_from_config(config: &AetherConfig) -> Result<Box<dyn MixingStrategy>> {
+    if let MixingConfig::BinomialPool { send_fraction, .. } = &config.mixnet.mixing {
+        if !(0.0..=1.0).contains(send_fraction) {
+            return Err(AetherError::Config(format!(
+                "Binomial pool send_fraction must be between 0 and 1, got {}",
+                send_fraction
+            )));
+        }
+    }
+
+    let strategy: Box<dyn MixingStrategy> = match &config.mixnet.mixing {
+        MixingConfig::StopAndGo => Box::new(StopAndGoMixing::new(config.poisson_lambda)),
+        MixingConfig::Timed { batch_interval_ms } => Box::new(TimedMixing {
+            batch_interval_ms: *batch_interval_ms,
+        }),
+        MixingConfig::Threshold { threshold } => Box::new(ThresholdMixing {
+            threshold: *threshold,
+        }),
+        MixingConfig::ThresholdOrTimed { threshold, batch_interval_ms } => Box::new(ThresholdOrTimedMixing {
+            threshold: *threshold,
+            batch_interval_ms: *batch_interval_ms,
+        }),
+        MixingConfig::BinomialPool { batch_interval_ms, min_pool, send_fraction } => Box::new(BinomialPoolMixing {
+            batch_interval_ms: *batch_interval_ms,
+            min_pool: *min_pool,
+            send_fraction: *send_fraction,
+        }),
+    };
+    Ok(strategy)
+}
+
+/// Run a pool strategy over a sequence of arrival times, returning every flush
+///
+/// Time advances in steps of `tick`, mirroring the node's processing loop,
+/// until the pool is empty after the last arrival or `max_duration` passes.
+/// Used to compare strategies empirically.
+pub fn simulate_pool(
+    strategy: &dyn MixingStrategy,
+    arrivals: &[Duration],
+    tick: Duration,
+    max_duration: Duration,
+) -> Vec<Flush> {
+    let mut flushes = Vec::new();
+    let mut pool = 0;
+    let mut next_arrival = 0;
+    let mut last_flush = Duration::ZERO;
+    let mut now = Duration::ZERO;
+    
+    while now <= max_duration && (next_arrival < arrivals.len() || pool > 0) {
+        while next_arrival < arrivals.len() && arrivals[next_arrival] <= now {
+            pool += 1;
+            next_arrival += 1;
+        }
+        
+        if let Some(flush) = strategy.flush(pool, now - last_flush) {
+            pool -= flush.released.min(pool);
+            last_flush = now;
+            flushes.push(flush);
+        }
+        now += tick;
+    }
+    
+    flushes
 }
 
 /// Stop-and-Go mixing with exponential delays
@@ -60,7 +167,7 @@ impl MixingStrategy for StopAndGoMixing {
     }
 }
 
-/// Timed mixing strategy that uses a fixed delay
+/// Timed mixing: the pool is flushed in full every `batch_interval_ms`
 pub struct TimedMixing {
     /// Fixed interval to wait before releasing a batch
     pub batch_interval_ms: u64,
@@ -74,4 +181,197 @@ impl MixingStrategy for TimedMixing {
     fn name(&self) -> &str {
         "Timed"
     }
+    
+    fn is_pool(&self) -> bool {
+        true
+    }
+    
+    fn flush(&self, pool_size: usize, elapsed: Duration) -> Option<Flush> {
+        (elapsed >= Duration::from_millis(self.batch_interval_ms)).then(|| Flush::all(pool_size))
+    }
+}
+
+/// Threshold mixing: the pool is flushed in full once it holds `threshold` packets
+pub struct ThresholdMixing {
+    /// Pool size that triggers a flush
+    pub threshold: usize,
+}
+
+impl MixingStrategy for ThresholdMixing {
+    fn calculate_delay(&self, _packet_size: usize, _current_load: f64) -> Duration {
+        Duration::ZERO
+    }
+    
+    fn name(&self) -> &str {
+        "Threshold"
+    }
+    
+    fn is_pool(&self) -> bool {
+        true
+    }
+    
+    fn flush(&self, pool_size: usize, _elapsed: Duration) -> Option<Flush> {
+        (pool_size >= self.threshold).then(|| Flush::all(pool_size))
+    }
+}
+
+/// Threshold-or-timed mixing: flush in full on whichever comes first, the
+/// threshold or the interval, bounding both latency and batch size
+pub struct ThresholdOrTimedMixing {
+    /// Pool size that triggers a flush
+    pub threshold: usize,
+    
+    /// Longest time between flushes
+    pub batch_interval_ms: u64,
+}
+
+impl MixingStrategy for ThresholdOrTimedMixing {
+    fn calculate_delay(&self, _packet_size: usize, _current_load: f64) -> Duration {
+        Duration::from_millis(self.batch_interval_ms)
+    }
+    
+    fn name(&self) -> &str {
+        "Threshold-or-Timed"
+    }
+    
+    fn is_pool(&self) -> bool {
+        true
+    }
+    
+    fn flush(&self, pool_size: usize, elapsed: Duration) -> Option<Flush> {
+        let timed_out = elapsed >= Duration::from_millis(self.batch_interval_ms);
+        (pool_size >= self.threshold || timed_out).then(|| Flush::all(pool_size))
+    }
+}
+
+/// Binomial (Cottrell-style timed dynamic pool) mixing
+///
+/// Every `batch_interval_ms` each pooled packet leaves independently with
+/// probability `send_fraction * (n - min_pool) / n`, so on average a fixed
+/// fraction of the excess over `min_pool` is sent. Packets may stay for
+/// several rounds, so any output could be any packet in the pool.
+pub struct BinomialPoolMixing {
+    /// Time between flush rounds
+    pub batch_interval_ms: u64,
+    
+    /// Packets always kept back
+    pub min_pool: usize,
+    
+    /// Expected fraction of the excess over `min_pool` sent per round
+    pub send_fraction: f64,
+}
+
+impl MixingStrategy for BinomialPoolMixing {
+    fn calculate_delay(&self, _packet_size: usize, _current_load: f64) -> Duration {
+        Duration::from_millis(self.batch_interval_ms)
+    }
+    
+    fn name(&self) -> &str {
+        "Binomial Pool"
+    }
+    
+    fn is_pool(&self) -> bool {
+        true
+    }
+    
+    fn flush(&self, pool_size: usize, elapsed: Duration) -> Option<Flush> {
+        if elapsed < Duration::from_millis(self.batch_interval_ms) {
+            return None;
+        }
+        
+        let excess = pool_size.saturating_sub(self.min_pool);
+        let released = if excess == 0 {
+            0
+        } else {
+            let p = (self.send_fraction.clamp(0.0, 1.0) * excess as f64 / pool_size as f64).min(1.0);
+            let binomial = Binomial::new(pool_size as u64, p).expect("probability in range");
+            (binomial.sample(&mut rand::thread_rng()) as usize).min(excess)
+        };
+        
+        Some(Flush { released, anonymity_set: pool_size })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_stop_and_go_bounds() {
+        let mixing = StopAndGoMixing::new(100.0);
+        for _ in 0..100 {
+            let delay = mixing.calculate_delay(1024, 1.0);
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(5000));
+        }
+        assert!(!mixing.is_pool());
+    }
+    
+    #[test]
+    fn test_timed_flushes_whole_pool() {
+        let mixing = TimedMixing { batch_interval_ms: 100 };
+        assert!(mixing.is_pool());
+        assert_eq!(mixing.flush(7, Duration::from_millis(50)), None);
+        assert_eq!(mixing.flush(7, Duration::from_millis(100)), Some(Flush::all(7)));
+        assert_eq!(mixing.flush(0, Duration::from_millis(150)), Some(Flush::all(0)));
+    }
+    
+    #[test]
+    fn test_threshold_or_timed() {
+        let mixing = ThresholdOrTimedMixing { threshold: 10, batch_interval_ms: 100 };
+        assert_eq!(mixing.flush(9, Duration::from_millis(50)), None);
+        assert_eq!(mixing.flush(10, Duration::from_millis(50)), Some(Flush::all(10)));
+        assert_eq!(mixing.flush(3, Duration::from_millis(100)), Some(Flush::all(3)));
+    }
+    
+    #[test]
+    fn test_binomial_pool_keeps_minimum() {
+        let mixing = BinomialPoolMixing { batch_interval_ms: 100, min_pool: 20, send_fraction: 1.0 };
+        assert_eq!(mixing.flush(50, Duration::from_millis(10)), None);
+        
+        for _ in 0..100 {
+            let flush = mixing.flush(50, Duration::from_millis(100)).unwrap();
+            assert!(flush.released <= 30);
+            assert_eq!(flush.anonymity_set, 50);
+        }
+        assert_eq!(mixing.flush(20, Duration::from_millis(100)).unwrap().released, 0);
+    }
+    
+    #[test]
+    fn test_simulated_anonymity_sets() {
+        // One packet every 10ms for two seconds
+        let arrivals: Vec<_> = (0..200).map(|i| Duration::from_millis(i * 10)).collect();
+        let tick = Duration::from_millis(10);
+        let max = Duration::from_secs(60);
+        
+        let threshold = simulate_pool(&ThresholdMixing { threshold: 20 }, &arrivals, tick, max);
+        assert_eq!(threshold.len(), 10);
+        assert!(threshold.iter().all(|f| f.anonymity_set == 20));
+        
+        let timed = simulate_pool(&TimedMixing { batch_interval_ms: 100 }, &arrivals, tick, max);
+        assert!(timed.iter().all(|f| f.anonymity_set <= 11));
+        assert_eq!(timed.iter().map(|f| f.released).sum::<usize>(), 200);
+        
+        // The pool keeps packets back, so its anonymity sets are larger
+        let pool = BinomialPoolMixing { batch_interval_ms: 100, min_pool: 20, send_fraction: 0.8 };
+        let flushes = simulate_pool(&pool, &arrivals, tick, Duration::from_secs(2));
+        let mean = |f: &[Flush]| f.iter().map(|f| f.anonymity_set).sum::<usize>() as f64 / f.len() as f64;
+        assert!(mean(&flushes) > mean(&timed));
+    }
+    
+    #[test]
+    fn test_strategy_from_config() {
+        let mut config = AetherConfig::default();
+        assert_eq!(strategy_from_config(&config).unwrap().name(), "Stop-and-Go");
+        
+        config.mixnet.mixing = MixingConfig::Timed { batch_interval_ms: 500 };
+        assert_eq!(strategy_from_config(&config).unwrap().name(), "Timed");
+        
+        config.mixnet.mixing = MixingConfig::BinomialPool { batch_interval_ms: 500, min_pool: 10, send_fraction: 0.5 };
+        assert_eq!(strategy_from_config(&config).unwrap().name(), "Binomial Pool");
+
+        for send_fraction in [f64::NAN, -0.1, 1.5] {
+            config.mixnet.mixing = MixingConfig::BinomialPool { batch_interval_ms: 500, min_pool: 10, send_fraction };
+            assert!(matches!(strategy_from_config(&config), Err(AetherError::Config(_))));
+        }
+    }
 }
@@ -4,7 +4,21 @@ pub mod node;
 pub mod mixing;
 pub mod traffic;
 pub mod loopix;
+pub mod loop_monitor;
+pub mod transport;
+pub mod queue;
+pub mod ratelimit;
+pub mod epoch;
+pub mod topology;
+pub mod directory;
 
-pub use node::{MixNode, NodeInfo, NodeRole};
-pub use mixing::{MixingStrategy, StopAndGoMixing};
-pub use traffic::{TrafficShaper, CoverTrafficGenerator};
+pub use node::{MixNode, NodeHandle, NodeInfo, NodeRole};
+pub use mixing::{
+    MixingStrategy, Flush, StopAndGoMixing, TimedMixing, ThresholdMixing, ThresholdOrTimedMixing,
+    BinomialPoolMixing, strategy_from_config, simulate_pool,
+};
+pub use traffic::{TrafficShaper, CoverTrafficGenerator, LinkId, LinkStats};
+pub use topology::{RegisteredNode, SignedTopology, Topology, TopologyConfig, TopologyManager};
+pub use directory::{
+    AuthoritySignature, DirectoryBuilder, DirectoryCache, DirectoryDocument, DirectoryVerifier, SignedDirectory,
+};
@@ -1,13 +1,40 @@
 //! Mix node implementation
 
 use crate::config::AetherConfig;
-use crate::crypto::kyber::{KeyPair, SecretKey};
-use crate::protocols::OutfoxPacket;
+use crate::crypto::hash::blake3_hash;
+use crate::crypto::kyber::PublicKey;
+use crate::protocols::{LayerOutcome, OutfoxPacket, ProcessedLayer, ReplayCache};
 use crate::error::{AetherError, Result};
+use crate::mixnet::epoch::{EpochKey, EpochKeys, EpochSchedule};
+use crate::mixnet::mixing::{strategy_from_config, Flush, MixingStrategy};
+use crate::mixnet::queue::PacketQueue;
+use crate::mixnet::ratelimit::PeerRateLimiter;
+use crate::mixnet::transport::{self, Connections};
+use crate::MAX_PACKET_SIZE;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use rand_distr::{Distribution, Exp};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::time::DelayQueue;
+
+/// Fraction of the incoming queue's capacity at which a node reports full
+/// load to its mixing strategy
+const LOAD_SATURATION_FRACTION: f64 = 0.1;
+
+/// Number of recent flushes kept for anonymity-set reporting
+const FLUSH_HISTORY: usize = 1024;
+
+/// Mean milliseconds between cover traffic draws; each draw emits a packet
+/// with probability `cover_traffic_ratio`
+const COVER_TICK_MS: f64 = 500.0;
 
 /// Role of a node in the network
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -46,38 +73,158 @@ pub struct NodeInfo {
     /// Network address
     pub address: String,
     
-    /// Public key for key encapsulation
+    /// Public key for key encapsulation in the current epoch
     pub public_key_bytes: Vec<u8>,
+    
+    /// Mix keys published for the current and next epochs
+    #[serde(default)]
+    pub epoch_keys: Vec<EpochKey>,
+}
+
+impl NodeInfo {
+    /// Public key to encrypt to in `epoch`, falling back to `public_key_bytes`
+    /// if no key was published for it
+    pub fn public_key_for(&self, epoch: u64) -> &[u8] {
+        self.epoch_keys
+            .iter()
+            .find(|key| key.epoch == epoch)
+            .map_or(&self.public_key_bytes, |key| &key.public_key_bytes)
+    }
+}
+
+/// A processed packet waiting to leave the node
+enum Ready {
+    /// Send to the node with this id
+    Forward(OutfoxPacket, [u8; 32]),
+    /// Cover generated by this node, sent like `Forward`
+    Cover(OutfoxPacket, [u8; 32]),
+    /// Deliver locally as the exit hop
+    Deliver(Vec<u8>),
+}
+
+/// A packet held in the pool of a batching strategy
+struct Pooled {
+    ready: Ready,
+    entered: Instant,
+}
+
+/// A packet handed to the release scheduler with its mixing delay
+struct Delayed {
+    ready: Ready,
+    delay: Duration,
+}
+
+/// Groups of node tasks, stopped in this order on shutdown so that each
+/// drains into the next
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    /// Listener, cover generation and key rotation
+    Intake,
+    /// Decryption workers
+    Processing,
+    /// Release scheduler or pool flusher
+    Release,
+    /// Sending to next hops
+    Forwarding,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Intake, Stage::Processing, Stage::Release, Stage::Forwarding];
+}
+
+/// Running tasks of each stage, and the tokens that stop them
+#[derive(Default)]
+struct Tasks {
+    cancel: [CancellationToken; Stage::ALL.len()],
+    handles: [Vec<JoinHandle<()>>; Stage::ALL.len()],
 }
 
 /// Mix node structure
 pub struct MixNode {
-    /// Node information
+    /// Node information, with the keys it was created with; see
+    /// `published_info` for the current ones
     pub info: NodeInfo,
     
-    /// Secret key for decryption
-    secret_key: SecretKey,
+    /// Mix keys of the current and next epochs, and of the previous epoch
+    /// during its grace window
+    keys: Arc<parking_lot::RwLock<EpochKeys>>,
     
     /// Configuration
     config: Arc<AetherConfig>,
     
-    /// Packet queues
-    incoming_queue: Arc<RwLock<VecDeque<OutfoxPacket>>>,
-    outgoing_queue: Arc<RwLock<VecDeque<OutfoxPacket>>>,
+    /// Bounded packet queues; outgoing packets carry the id of their next hop
+    incoming_queue: Arc<PacketQueue<OutfoxPacket>>,
+    outgoing_queue: Arc<PacketQueue<(OutfoxPacket, [u8; 32])>>,
+    
+    /// Per-peer limit on packets accepted by the listener
+    rate_limiter: Arc<PeerRateLimiter>,
+    
+    /// Messages delivered to this node as the exit hop
+    delivered_queue: Arc<RwLock<VecDeque<Vec<u8>>>>,
+    
+    /// Replay tags of packets already processed
+    replay_cache: Arc<ReplayCache>,
+    
+    /// How packets are delayed or batched
+    mixing: Arc<dyn MixingStrategy>,
+    
+    /// Packets held by a pool strategy, and when it last flushed
+    pool: Arc<RwLock<Vec<Pooled>>>,
+    last_flush: Arc<RwLock<Instant>>,
+    
+    /// Recent flushes of the pool strategy
+    flush_history: Arc<RwLock<VecDeque<Flush>>>,
+    
+    /// Delayed packets on their way to the release scheduler; the receiver
+    /// is taken when processing starts
+    delayed_tx: mpsc::UnboundedSender<Delayed>,
+    delayed_rx: Arc<Mutex<Option<mpsc::UnboundedReceiver<Delayed>>>>,
+    
+    /// Known nodes, by node id
+    peers: Arc<RwLock<HashMap<[u8; 32], NodeInfo>>>,
+    
+    /// Node ids by the hash of each key they published, which is how a
+    /// packet names its next hop
+    hops: Arc<RwLock<HashMap<[u8; 32], [u8; 32]>>>,
+    
+    /// Secret payload of this node's cover loops, recognised when they return
+    cover_tag: [u8; 32],
+    
+    /// Open connections to next hops
+    connections: Arc<Connections>,
+    
+    /// Spawned tasks, by pipeline stage
+    tasks: Arc<Mutex<Tasks>>,
     
     /// Statistics
     packets_processed: Arc<RwLock<u64>>,
     total_latency_ms: Arc<RwLock<u64>>,
+    replays_dropped: Arc<RwLock<u64>>,
+    cover_packets_sent: Arc<RwLock<u64>>,
+    cover_loops_returned: Arc<RwLock<u64>>,
 }
 
 impl MixNode {
-    /// Create a new mix node
+    /// Create a new mix node using the mixing strategy selected in `config`
     pub fn new(
         layer: usize,
         role: NodeRole,
         stake: u64,
         address: String,
         config: Arc<AetherConfig>,
+    ) -> Result<Self> {
+        let mixing = strategy_from_config(&config)?;
+        Self::with_strategy(layer, role, stake, address, config, mixing)
+    }
+    
+    /// Create a new mix node with an explicit mixing strategy
+    pub fn with_strategy(
+        layer: usize,
+        role: NodeRole,
+        stake: u64,
+        address: String,
+        config: Arc<AetherConfig>,
+        mixing: Box<dyn MixingStrategy>,
     ) -> Result<Self> {
         if layer == 0 || layer > config.mixnet_layers {
             return Err(AetherError::Config(
@@ -85,36 +232,75 @@ impl MixNode {
             ));
         }
         
-        // Generate node identity
-        let key_pair = KeyPair::generate();
-        let mut id = [0u8; 32];
-        id.copy_from_slice(&crate::crypto::hash::blake3_hash(
-            key_pair.public_key.as_bytes()
-        ));
+        // Generate the first epoch's keys; the node keeps its id across rotations
+        let keys = EpochKeys::new(EpochSchedule::from_config(&config.mixnet), SystemTime::now());
+        let public_key = keys.current_public_key().as_bytes();
         
         let info = NodeInfo {
-            id,
+            id: blake3_hash(public_key),
             layer,
             role,
             reputation: 1.0, // Start with full reputation
             stake,
             address,
-            public_key_bytes: key_pair.public_key.as_bytes().to_vec(),
+            public_key_bytes: public_key.to_vec(),
+            epoch_keys: keys.published(),
+        };
+        
+        let replay_cache = match &config.mixnet.replay_cache_path {
+            Some(path) => ReplayCache::with_persistence(config.mixnet.replay_cache_capacity, path)?,
+            None => ReplayCache::new(config.mixnet.replay_cache_capacity),
         };
+        if replay_cache.epoch() != keys.epoch() {
+            replay_cache.rotate(keys.epoch());
+        }
+        
+        let (delayed_tx, delayed_rx) = mpsc::unbounded_channel();
+        let mut cover_tag = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut cover_tag);
         
         Ok(Self {
             info,
-            secret_key: key_pair.secret_key,
-            config,
-            incoming_queue: Arc::new(RwLock::new(VecDeque::new())),
-            outgoing_queue: Arc::new(RwLock::new(VecDeque::new())),
+            keys: Arc::new(parking_lot::RwLock::new(keys)),
+            incoming_queue: Arc::new(PacketQueue::bounded(
+                config.mixnet.queue_capacity,
+                config.mixnet.drop_policy,
+            )),
+            outgoing_queue: Arc::new(PacketQueue::bounded(
+                config.mixnet.queue_capacity,
+                config.mixnet.drop_policy,
+            )),
+            rate_limiter: Arc::new(PeerRateLimiter::new(
+                config.mixnet.peer_rate_limit,
+                config.mixnet.peer_burst,
+            )),
+            delivered_queue: Arc::new(RwLock::new(VecDeque::new())),
+            replay_cache: Arc::new(replay_cache),
+            mixing: Arc::from(mixing),
+            pool: Arc::new(RwLock::new(Vec::new())),
+            last_flush: Arc::new(RwLock::new(Instant::now())),
+            flush_history: Arc::new(RwLock::new(VecDeque::new())),
+            delayed_tx,
+            delayed_rx: Arc::new(Mutex::new(Some(delayed_rx))),
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            hops: Arc::new(RwLock::new(HashMap::new())),
+            cover_tag,
+            connections: Arc::new(Connections::new()),
+            tasks: Arc::new(Mutex::new(Tasks::default())),
             packets_processed: Arc::new(RwLock::new(0)),
             total_latency_ms: Arc::new(RwLock::new(0)),
+            replays_dropped: Arc::new(RwLock::new(0)),
+            cover_packets_sent: Arc::new(RwLock::new(0)),
+            cover_loops_returned: Arc::new(RwLock::new(0)),
+            config,
         })
     }
     
     /// Start the mix node
-    pub async fn run(&self) -> Result<()> {
+    ///
+    /// Returns a handle for shutting the node down; dropping it leaves the
+    /// node running.
+    pub async fn run(&self) -> Result<NodeHandle> {
         tracing::info!(
             "Starting mix node {} on layer {} as {:?}",
             hex::encode(&self.info.id[..8]),
@@ -122,112 +308,499 @@ impl MixNode {
             self.info.role
         );
         
-        // Spawn packet processing task
+        // Accept packets from other nodes and clients
+        let listener = transport::bind(&self.info.address).await?;
+        let incoming = Arc::clone(&self.incoming_queue);
+        let limiter = Arc::clone(&self.rate_limiter);
+        self.spawn(Stage::Intake, |cancel| transport::accept_loop(listener, incoming, limiter, cancel));
+        
+        self.start_processing();
+        
+        // Spawn forwarding task
         let self_clone = self.clone_arc_fields();
-        tokio::spawn(async move {
+        self.spawn(Stage::Forwarding, |cancel| async move {
             loop {
-                if let Err(e) = self_clone.process_packets().await {
-                    tracing::error!("Packet processing error: {}", e);
+                let (packet, next_hop) = tokio::select! {
+                    next = self_clone.outgoing_queue.pop_wait() => next,
+                    // Everything already released still goes out
+                    _ = cancel.cancelled() => match self_clone.outgoing_queue.pop() {
+                        Some(next) => next,
+                        None => break,
+                    },
+                };
+                self_clone.forward_packet(packet, next_hop).await;
+            }
+        });
+        
+        // Rotate mix keys at epoch boundaries and erase them after the grace window
+        let self_clone = self.clone_arc_fields();
+        self.spawn(Stage::Intake, |cancel| async move {
+            loop {
+                let wait = self_clone.keys.read().next_change(SystemTime::now());
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => self_clone.advance_epoch(SystemTime::now()),
+                    _ = cancel.cancelled() => break,
                 }
-                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
             }
         });
         
         // Spawn cover traffic generator
         let self_clone = self.clone_arc_fields();
-        tokio::spawn(async move {
+        self.spawn(Stage::Intake, |cancel| async move {
+            // Exponential gaps make the draws, and so the emitted cover, a Poisson process
+            let gaps = Exp::new(1.0 / COVER_TICK_MS).unwrap();
             loop {
-                self_clone.generate_cover_traffic().await;
-                let delay = rand::random::<u64>() % 1000;
-                tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+                if let Err(e) = self_clone.generate_cover_traffic().await {
+                    tracing::warn!("Failed to generate cover traffic: {}", e);
+                }
+                let delay = gaps.sample(&mut rand::thread_rng());
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(delay as u64)) => {}
+                    _ = cancel.cancelled() => break,
+                }
             }
         });
         
-        Ok(())
+        Ok(NodeHandle { node: self.clone_arc_fields() })
     }
     
-    /// Process packets from the incoming queue
-    async fn process_packets(&self) -> Result<()> {
-        let mut incoming = self.incoming_queue.write().await;
+    /// Start the packet pipeline: decryption workers feeding either the
+    /// release scheduler or the pool of a batching strategy
+    ///
+    /// Called by `run`; exposed so the pipeline can be driven without a
+    /// listener. Only the first call has any effect.
+    pub fn start_processing(&self) {
+        let Some(delayed) = self.delayed_rx.lock().take() else {
+            return;
+        };
+        let workers = match self.config.mixnet.processing_workers {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
         
-        if let Some(mut packet) = incoming.pop_front() {
-            drop(incoming); // Release lock
-            
-            // Verify integrity
-            if !packet.verify_integrity() {
-                tracing::warn!("Dropping packet with invalid integrity");
+        // Workers take packets as they arrive; none of them ever waits out a delay
+        for _ in 0..workers {
+            let self_clone = self.clone_arc_fields();
+            self.spawn(Stage::Processing, |cancel| async move {
+                loop {
+                    let packet = tokio::select! {
+                        packet = self_clone.incoming_queue.pop_wait() => packet,
+                        // Packets already accepted are still mixed
+                        _ = cancel.cancelled() => match self_clone.incoming_queue.pop() {
+                            Some(packet) => packet,
+                            None => break,
+                        },
+                    };
+                    if let Err(e) = self_clone.process_packet(packet).await {
+                        tracing::error!("Packet processing error: {}", e);
+                    }
+                }
+            });
+        }
+        
+        if self.mixing.is_pool() {
+            // Time-based strategies also flush when no packet arrives
+            let self_clone = self.clone_arc_fields();
+            self.spawn(Stage::Release, |cancel| async move {
+                loop {
+                    self_clone.flush_pool().await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+                        _ = cancel.cancelled() => break,
+                    }
+                }
+                self_clone.drain_pool().await;
+            });
+        } else {
+            let self_clone = self.clone_arc_fields();
+            self.spawn(Stage::Release, |cancel| async move {
+                self_clone.schedule_releases(delayed, cancel).await
+            });
+        }
+    }
+    
+    /// Spawn a task of `stage`, handing it the token that stops the stage
+    fn spawn<F>(&self, stage: Stage, task: impl FnOnce(CancellationToken) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.tasks.lock();
+        let cancel = tasks.cancel[stage as usize].clone();
+        tasks.handles[stage as usize].push(tokio::spawn(task(cancel)));
+    }
+    
+    /// Stop the node, draining every packet it holds first
+    ///
+    /// The listener closes and cover generation and key rotation stop.
+    /// Packets already accepted are still decrypted, wait out their mixing
+    /// delays (a pool strategy releases its last batch at once) and are
+    /// forwarded. The mix keys are then erased, and the replay cache and
+    /// final statistics persisted where configured. The node cannot be
+    /// started again.
+    pub async fn shutdown(&self) -> Result<NodeStats> {
+        // Each stage drains into the next, so they stop in pipeline order
+        for stage in Stage::ALL {
+            let (cancel, handles) = {
+                let mut tasks = self.tasks.lock();
+                (tasks.cancel[stage as usize].clone(), std::mem::take(&mut tasks.handles[stage as usize]))
+            };
+            cancel.cancel();
+            for handle in handles {
+                if let Err(e) = handle.await {
+                    tracing::error!("{:?} task failed during shutdown: {}", stage, e);
+                }
+            }
+        }
+        
+        self.keys.write().erase();
+        self.replay_cache.persist()?;
+        let stats = self.get_stats().await;
+        if let Some(path) = &self.config.mixnet.stats_path {
+            std::fs::write(path, serde_json::to_vec_pretty(&stats)?)?;
+        }
+        
+        tracing::info!("Mix node {} shut down", hex::encode(&self.info.id[..8]));
+        Ok(stats)
+    }
+    
+    /// Decrypt one packet and hand it to the mixing strategy
+    pub async fn process_packet(&self, mut packet: OutfoxPacket) -> Result<()> {
+        // Process the packet layer; this also authenticates the header
+        let processed = self.process_layer(&mut packet)?;
+        
+        // Drop packets whose shared secret we have already seen, and every
+        // packet once this epoch's replay budget is spent
+        if !self.replay_cache.check_and_insert(processed.replay_tag) {
+            if self.replay_cache.is_full() {
+                tracing::warn!("Replay cache full, refusing packets until the next epoch");
+            } else {
+                tracing::warn!("Dropping replayed packet");
+            }
+            *self.replays_dropped.write().await += 1;
+            return Ok(());
+        }
+        
+        let ready = match processed.outcome {
+            LayerOutcome::Forward { next_hop_hash } => Ready::Forward(packet, next_hop_hash),
+            LayerOutcome::Final { message } if message == self.cover_tag => {
+                *self.cover_loops_returned.write().await += 1;
                 return Ok(());
             }
+            LayerOutcome::Final { message } => Ready::Deliver(message),
+        };
+        
+        self.mix(ready).await;
+        Ok(())
+    }
+    
+    /// Peel one layer with the current key, or the previous one during the
+    /// grace window
+    fn process_layer(&self, packet: &mut OutfoxPacket) -> Result<ProcessedLayer> {
+        let keys = self.keys.read();
+        let mut result = Err(AetherError::Crypto("No mix key".to_string()));
+        for secret_key in keys.secret_keys() {
+            // A wrong key fails authentication before the packet is modified
+            result = packet.process_layer(secret_key);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+    
+    /// Rotate to a new key epoch if one has begun at `now`, starting a new
+    /// replay epoch with it, and erase a previous key past its grace window
+    fn advance_epoch(&self, now: SystemTime) {
+        let Some(epoch) = self.keys.write().advance(now) else {
+            return;
+        };
+        self.replay_cache.rotate(epoch);
+        tracing::info!("Mix node {} rotated to key epoch {}", hex::encode(&self.info.id[..8]), epoch);
+    }
+    
+    /// Hand a packet to the mixing strategy
+    async fn mix(&self, ready: Ready) {
+        if self.mixing.is_pool() {
+            self.pool.write().await.push(Pooled { ready, entered: Instant::now() });
+            self.flush_pool().await;
+        } else {
+            let delay = self.mixing.calculate_delay(MAX_PACKET_SIZE, self.current_load());
+            // Only fails once the scheduler has stopped, when there is nowhere to release to
+            let _ = self.delayed_tx.send(Delayed { ready, delay });
+        }
+    }
+    
+    /// Release each delayed packet once its own delay has passed
+    ///
+    /// All pending packets sit in one timer wheel, so a long delay never
+    /// holds back a packet due earlier.
+    ///
+    /// Once `cancel` fires, packets already handed over still wait out
+    /// their delays, and it returns when the last one is released.
+    async fn schedule_releases(
+        &self,
+        mut delayed: mpsc::UnboundedReceiver<Delayed>,
+        cancel: CancellationToken,
+    ) {
+        let mut pending = DelayQueue::new();
+        loop {
+            tokio::select! {
+                Some(Delayed { ready, delay }) = delayed.recv() => {
+                    pending.insert((ready, delay), delay);
+                }
+                Some(expired) = std::future::poll_fn(|cx| pending.poll_expired(cx)) => {
+                    let (ready, delay) = expired.into_inner();
+                    self.release(ready, delay).await;
+                }
+                _ = cancel.cancelled() => break,
+            }
+        }
+        
+        delayed.close();
+        while let Some(Delayed { ready, delay }) = delayed.recv().await {
+            pending.insert((ready, delay), delay);
+        }
+        while let Some(expired) = std::future::poll_fn(|cx| pending.poll_expired(cx)).await {
+            let (ready, delay) = expired.into_inner();
+            self.release(ready, delay).await;
+        }
+    }
+    
+    /// Let a pool strategy release a batch, if it decides to
+    async fn flush_pool(&self) {
+        if !self.mixing.is_pool() {
+            return;
+        }
+        
+        let batch: Vec<Pooled> = {
+            let mut pool = self.pool.write().await;
+            let mut last_flush = self.last_flush.write().await;
+            let Some(flush) = self.mixing.flush(pool.len(), last_flush.elapsed()) else {
+                return;
+            };
+            *last_flush = Instant::now();
             
-            // Process the packet layer
-            packet.process_layer(&self.secret_key)?;
-            
-            // Apply mixing delay (stop-and-go)
-            let delay_ms = self.calculate_mixing_delay();
-            tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
-            
-            // Add to outgoing queue
-            let mut outgoing = self.outgoing_queue.write().await;
-            outgoing.push_back(packet);
-            
-            // Update statistics
-            let mut processed = self.packets_processed.write().await;
-            *processed += 1;
+            // Rounds that release nothing hide no packet, so they don't count
+            if flush.released > 0 {
+                let mut history = self.flush_history.write().await;
+                if history.len() == FLUSH_HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(flush);
+            }
             
-            let mut latency = self.total_latency_ms.write().await;
-            *latency += delay_ms;
+            // Release a uniformly random subset so arrival order reveals nothing
+            pool.shuffle(&mut rand::thread_rng());
+            let count = flush.released.min(pool.len());
+            pool.drain(..count).collect()
+        };
+        
+        for pooled in batch {
+            self.release(pooled.ready, pooled.entered.elapsed()).await;
+        }
+    }
+    
+    /// Release everything left in the pool at once, on shutdown
+    async fn drain_pool(&self) {
+        let mut batch = std::mem::take(&mut *self.pool.write().await);
+        batch.shuffle(&mut rand::thread_rng());
+        for pooled in batch {
+            self.release(pooled.ready, pooled.entered.elapsed()).await;
+        }
+    }
+    
+    /// Hand a mixed packet to the forwarder or the local delivery queue
+    async fn release(&self, ready: Ready, latency: Duration) {
+        match ready {
+            Ready::Forward(packet, next_hop) => {
+                if !self.outgoing_queue.push((packet, next_hop)) {
+                    tracing::debug!("Outgoing queue full, dropping packet");
+                }
+            }
+            Ready::Cover(packet, next_hop) => {
+                // Cover is counted when generated and stays out of the real-traffic statistics
+                if !self.outgoing_queue.push_cover((packet, next_hop)) {
+                    tracing::debug!("Outgoing queue full, dropping cover packet");
+                }
+                return;
+            }
+            Ready::Deliver(message) => {
+                self.delivered_queue.write().await.push_back(message);
+            }
         }
         
-        Ok(())
+        // Update statistics
+        *self.packets_processed.write().await += 1;
+        *self.total_latency_ms.write().await += latency.as_millis() as u64;
     }
     
-    /// Calculate mixing delay using Poisson distribution
-    fn calculate_mixing_delay(&self) -> u64 {
-        use rand_distr::{Distribution, Exp};
-        let mut rng = rand::thread_rng();
-        let exp = Exp::new(1.0 / self.config.poisson_lambda).unwrap();
-        let delay = exp.sample(&mut rng);
-        delay as u64
+    /// Load reported to the mixing strategy, from 0.0 (idle) to 1.0 (saturated)
+    fn current_load(&self) -> f64 {
+        let queued = self.incoming_queue.len();
+        let saturation = (self.config.mixnet.queue_capacity as f64 * LOAD_SATURATION_FRACTION).max(1.0);
+        (queued as f64 / saturation).min(1.0)
+    }
+    
+    /// Send a packet to its next hop
+    async fn forward_packet(&self, packet: OutfoxPacket, next_hop: [u8; 32]) {
+        let address = {
+            let hops = self.hops.read().await;
+            let peers = self.peers.read().await;
+            hops.get(&next_hop).and_then(|id| peers.get(id)).map(|peer| peer.address.clone())
+        };
+        let Some(address) = address else {
+            tracing::warn!("Dropping packet for unknown next hop {}", hex::encode(&next_hop[..8]));
+            return;
+        };
+        
+        if let Err(e) = self.connections.send(&address, &packet).await {
+            tracing::warn!("Failed to forward packet: {}", e);
+        }
     }
     
-    /// Generate cover traffic
-    async fn generate_cover_traffic(&self) {
-        if rand::random::<f64>() < self.config.cover_traffic_ratio {
-            // Create a dummy packet (implementation simplified)
-            tracing::debug!("Generating cover traffic");
-            // In a real implementation, dummy packets would be created here
+    /// Possibly emit one cover packet, with probability `cover_traffic_ratio`
+    ///
+    /// The packet is a full-length Outfox packet that loops once through the
+    /// layered topology, starting at a random node of the next layer and
+    /// ending back here, and it is mixed like any forwarded packet. Nothing
+    /// is sent while some layer has no known node.
+    async fn generate_cover_traffic(&self) -> Result<()> {
+        if rand::random::<f64>() >= self.config.cover_traffic_ratio {
+            return Ok(());
         }
+        
+        let Some((route, first_hop)) = self.cover_route().await? else {
+            tracing::debug!("No complete loop through the topology, skipping cover traffic");
+            return Ok(());
+        };
+        
+        let packet = OutfoxPacket::new(&self.cover_tag, &route)?;
+        self.mix(Ready::Cover(packet, first_hop)).await;
+        *self.cover_packets_sent.write().await += 1;
+        Ok(())
+    }
+    
+    /// Pick one random known node from every other layer, in layer order
+    /// after this node's own, and close the loop with this node
+    async fn cover_route(&self) -> Result<Option<(Vec<PublicKey>, [u8; 32])>> {
+        let layers = self.config.mixnet_layers;
+        let (epoch, own_key) = {
+            let keys = self.keys.read();
+            (keys.epoch(), keys.current_public_key().clone())
+        };
+        let peers = self.peers.read().await;
+        let mut rng = rand::thread_rng();
+        
+        let mut route = Vec::with_capacity(layers);
+        let mut first_hop = None;
+        for step in 1..layers {
+            let layer = (self.info.layer - 1 + step) % layers + 1;
+            let candidates: Vec<&NodeInfo> = peers.values().filter(|peer| peer.layer == layer).collect();
+            let Some(hop) = candidates.choose(&mut rng) else {
+                return Ok(None);
+            };
+            // Forwarding resolves the first hop by key hash, like any next hop
+            let public_key = hop.public_key_for(epoch);
+            first_hop.get_or_insert(blake3_hash(public_key));
+            route.push(PublicKey::from_bytes(public_key)?);
+        }
+        
+        let Some(first_hop) = first_hop else {
+            return Ok(None);
+        };
+        route.push(own_key);
+        Ok(Some((route, first_hop)))
     }
     
     /// Helper to clone Arc fields for spawning tasks
     fn clone_arc_fields(&self) -> Self {
         Self {
             info: self.info.clone(),
-            secret_key: self.secret_key.clone(),
+            keys: Arc::clone(&self.keys),
             config: Arc::clone(&self.config),
             incoming_queue: Arc::clone(&self.incoming_queue),
             outgoing_queue: Arc::clone(&self.outgoing_queue),
+            rate_limiter: Arc::clone(&self.rate_limiter),
+            delivered_queue: Arc::clone(&self.delivered_queue),
+            replay_cache: Arc::clone(&self.replay_cache),
+            mixing: Arc::clone(&self.mixing),
+            pool: Arc::clone(&self.pool),
+            last_flush: Arc::clone(&self.last_flush),
+            flush_history: Arc::clone(&self.flush_history),
+            delayed_tx: self.delayed_tx.clone(),
+            delayed_rx: Arc::clone(&self.delayed_rx),
+            peers: Arc::clone(&self.peers),
+            hops: Arc::clone(&self.hops),
+            cover_tag: self.cover_tag,
+            connections: Arc::clone(&self.connections),
+            tasks: Arc::clone(&self.tasks),
             packets_processed: Arc::clone(&self.packets_processed),
             total_latency_ms: Arc::clone(&self.total_latency_ms),
+            replays_dropped: Arc::clone(&self.replays_dropped),
+            cover_packets_sent: Arc::clone(&self.cover_packets_sent),
+            cover_loops_returned: Arc::clone(&self.cover_loops_returned),
         }
     }
     
-    /// Add a packet to the incoming queue
+    /// Add a packet to the incoming queue, unless its drop policy refuses it
     pub async fn receive_packet(&self, packet: OutfoxPacket) {
-        let mut queue = self.incoming_queue.write().await;
-        queue.push_back(packet);
+        self.incoming_queue.push(packet);
     }
     
     /// Get a packet from the outgoing queue
     pub async fn send_packet(&self) -> Option<OutfoxPacket> {
-        let mut queue = self.outgoing_queue.write().await;
+        self.outgoing_queue.pop().map(|(packet, _)| packet)
+    }
+    
+    /// Register a node this one may forward packets to, or update its keys
+    pub async fn add_peer(&self, peer: &NodeInfo) {
+        let mut hops = self.hops.write().await;
+        hops.retain(|_, id| *id != peer.id);
+        let published = peer.epoch_keys.iter().map(|key| &key.public_key_bytes);
+        for public_key in std::iter::once(&peer.public_key_bytes).chain(published) {
+            hops.insert(blake3_hash(public_key), peer.id);
+        }
+        self.peers.write().await.insert(peer.id, peer.clone());
+    }
+    
+    /// This node's information with the keys of the current and next epochs,
+    /// as announced to peers
+    pub fn published_info(&self) -> NodeInfo {
+        let keys = self.keys.read();
+        NodeInfo {
+            public_key_bytes: keys.current_public_key().as_bytes().to_vec(),
+            epoch_keys: keys.published(),
+            ..self.info.clone()
+        }
+    }
+    
+    /// Get a message delivered to this node as the exit hop
+    pub async fn receive_message(&self) -> Option<Vec<u8>> {
+        let mut queue = self.delivered_queue.write().await;
         queue.pop_front()
     }
     
+    /// Write the replay cache to disk, if persistence is configured
+    pub fn persist_replay_cache(&self) -> Result<()> {
+        self.replay_cache.persist()
+    }
+    
+    /// Recent flushes of the pool strategy that released packets, oldest first
+    pub async fn flush_history(&self) -> Vec<Flush> {
+        self.flush_history.read().await.iter().copied().collect()
+    }
+    
     /// Get node statistics
     pub async fn get_stats(&self) -> NodeStats {
         let processed = *self.packets_processed.read().await;
         let total_latency = *self.total_latency_ms.read().await;
+        let key_epoch = self.keys.read().epoch();
+        let history = self.flush_history.read().await;
+        let mean_anonymity_set = if history.is_empty() {
+            0.0
+        } else {
+            history.iter().map(|f| f.anonymity_set).sum::<usize>() as f64 / history.len() as f64
+        };
         
         NodeStats {
             packets_processed: processed,
@@ -237,11 +810,33 @@ impl MixNode {
                 0
             },
             reputation: self.info.reputation,
-            queue_size: self.incoming_queue.read().await.len(),
+            queue_size: self.incoming_queue.len(),
+            replays_dropped: *self.replays_dropped.read().await,
+            cover_packets_sent: *self.cover_packets_sent.read().await,
+            cover_loops_returned: *self.cover_loops_returned.read().await,
+            incoming_dropped: self.incoming_queue.dropped(),
+            outgoing_dropped: self.outgoing_queue.dropped(),
+            rate_limited: self.rate_limiter.limited(),
+            key_epoch,
+            mean_anonymity_set,
         }
     }
 }
 
+/// Handle to a node started by `MixNode::run`
+pub struct NodeHandle {
+    node: MixNode,
+}
+
+impl NodeHandle {
+    /// Shut the node down gracefully, returning its final statistics
+    ///
+    /// See `MixNode::shutdown`.
+    pub async fn shutdown(self) -> Result<NodeStats> {
+        self.node.shutdown().await
+    }
+}
+
 /// Node statistics
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NodeStats {
@@ -249,12 +844,53 @@ pub struct NodeStats {
     pub average_latency_ms: u64,
     pub reputation: f64,
     pub queue_size: usize,
+    /// Packets dropped because their replay tag was already seen
+    pub replays_dropped: u64,
+    /// Cover packets this node generated
+    pub cover_packets_sent: u64,
+    /// Cover packets that came back to this node after their loop
+    pub cover_loops_returned: u64,
+    /// Packets the incoming queue's drop policy refused or evicted
+    pub incoming_dropped: u64,
+    /// Packets the outgoing queue's drop policy refused or evicted
+    pub outgoing_dropped: u64,
+    /// Packets refused by per-peer rate limiting
+    pub rate_limited: u64,
+    /// Epoch of the node's current mix key
+    pub key_epoch: u64,
+    /// Mean anonymity-set size over recent flushes (0 for per-packet strategies)
+    pub mean_anonymity_set: f64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    /// Wait until the node has delivered `count` messages, returning them in arrival order
+    async fn wait_for_messages(node: &MixNode, count: usize) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while messages.len() < count && Instant::now() < deadline {
+            match node.receive_message().await {
+                Some(message) => messages.push(message),
+                None => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+        messages
+    }
+    
+    /// Wait until the node has a packet ready to forward
+    async fn wait_for_outgoing(node: &MixNode) -> Option<OutfoxPacket> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(packet) = node.send_packet().await {
+                return Some(packet);
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        None
+    }
+    
     #[tokio::test]
     async fn test_mix_node_creation() {
         let config = Arc::new(AetherConfig::default());
@@ -270,4 +906,292 @@ mod tests {
         assert_eq!(node.info.stake, 1000);
         assert_eq!(node.info.reputation, 1.0);
     }
+    
+    #[tokio::test]
+    async fn test_replayed_packet_dropped() {
+        let config = Arc::new(AetherConfig::default());
+        let node = MixNode::new(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9091".to_string(),
+            config,
+        ).unwrap();
+        
+        let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+        let packet = OutfoxPacket::new(b"replay me", &route).unwrap();
+        
+        node.receive_packet(packet.clone()).await;
+        node.receive_packet(packet).await;
+        node.start_processing();
+        
+        assert_eq!(wait_for_messages(&node, 1).await, vec![b"replay me".to_vec()]);
+        let stats = node.get_stats().await;
+        assert_eq!(stats.packets_processed, 1);
+        assert_eq!(stats.replays_dropped, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_previous_epoch_key_accepted_during_grace() {
+        let config = Arc::new(AetherConfig::default());
+        let node = MixNode::new(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9097".to_string(),
+            Arc::clone(&config),
+        ).unwrap();
+        node.start_processing();
+        
+        let announced = node.published_info();
+        let next = announced.epoch_keys[1].clone();
+        let old_route = vec![PublicKey::from_bytes(&announced.public_key_bytes).unwrap()];
+        let next_route = vec![PublicKey::from_bytes(&next.public_key_bytes).unwrap()];
+        
+        // Rotate at the start of the next epoch
+        let schedule = EpochSchedule::from_config(&config.mixnet);
+        node.advance_epoch(schedule.start(next.epoch));
+        assert_eq!(node.published_info().public_key_bytes, next.public_key_bytes);
+        assert_eq!(node.get_stats().await.key_epoch, next.epoch);
+        assert_eq!(node.replay_cache.epoch(), next.epoch);
+        
+        // Packets built before the rotation still open during the grace window
+        node.process_packet(OutfoxPacket::new(b"in flight", &old_route).unwrap()).await.unwrap();
+        node.process_packet(OutfoxPacket::new(b"next epoch", &next_route).unwrap()).await.unwrap();
+        let mut messages = wait_for_messages(&node, 2).await;
+        messages.sort();
+        assert_eq!(messages, vec![b"in flight".to_vec(), b"next epoch".to_vec()]);
+        
+        // Afterwards the old secret key is gone
+        node.advance_epoch(schedule.start(next.epoch) + schedule.grace());
+        let late = OutfoxPacket::new(b"too late", &old_route).unwrap();
+        assert!(node.process_packet(late).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_shutdown_drains_packets_and_leaks_no_tasks() {
+        use crate::mixnet::mixing::StopAndGoMixing;
+        
+        let dir = std::env::temp_dir().join(format!("aether-shutdown-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = AetherConfig::default();
+        config.mixnet.replay_cache_path = Some(dir.join("replay.bin").to_string_lossy().into_owned());
+        config.mixnet.stats_path = Some(dir.join("stats.json").to_string_lossy().into_owned());
+        let strategy = StopAndGoMixing { mean_delay_ms: 200.0, min_delay_ms: 200, max_delay_ms: 200 };
+        let node = MixNode::with_strategy(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:0".to_string(),
+            Arc::new(config),
+            Box::new(strategy),
+        ).unwrap();
+        
+        let handle = node.run().await.unwrap();
+        let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+        for i in 0..5u8 {
+            node.receive_packet(OutfoxPacket::new(&[i], &route).unwrap()).await;
+        }
+        
+        // Queued and delayed packets are delivered, not lost
+        let stats = handle.shutdown().await.unwrap();
+        assert_eq!(stats.packets_processed, 5);
+        assert_eq!(wait_for_messages(&node, 5).await.len(), 5);
+        
+        // Every task held its own clone of the node's shared state
+        assert_eq!(Arc::strong_count(&node.incoming_queue), 1);
+        assert_eq!(Arc::strong_count(&node.tasks), 1);
+        
+        // Secrets are gone and state is on disk
+        let late = OutfoxPacket::new(b"late", &route).unwrap();
+        assert!(node.process_packet(late).await.is_err());
+        assert!(dir.join("replay.bin").exists());
+        let saved: NodeStats = serde_json::from_slice(&std::fs::read(dir.join("stats.json")).unwrap()).unwrap();
+        assert_eq!(saved.packets_processed, 5);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_full_incoming_queue_counts_drops() {
+        let mut config = AetherConfig::default();
+        config.mixnet.queue_capacity = 2;
+        let node = MixNode::new(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9094".to_string(),
+            Arc::new(config),
+        ).unwrap();
+        
+        let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+        for i in 0..3u8 {
+            node.receive_packet(OutfoxPacket::new(&[i], &route).unwrap()).await;
+        }
+        
+        let stats = node.get_stats().await;
+        assert_eq!(stats.queue_size, 2);
+        assert_eq!(stats.incoming_dropped, 1);
+        assert_eq!(stats.outgoing_dropped, 0);
+    }
+    
+    #[tokio::test]
+    async fn test_delays_overlap_instead_of_queueing() {
+        use crate::mixnet::mixing::StopAndGoMixing;
+        
+        // Every packet is held for exactly 300ms
+        let strategy = StopAndGoMixing { mean_delay_ms: 300.0, min_delay_ms: 300, max_delay_ms: 300 };
+        let config = Arc::new(AetherConfig::default());
+        let node = MixNode::with_strategy(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9093".to_string(),
+            config,
+            Box::new(strategy),
+        ).unwrap();
+        
+        let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+        for i in 0..20u8 {
+            node.receive_packet(OutfoxPacket::new(&[i], &route).unwrap()).await;
+        }
+        
+        // Sleeping out each delay in turn would take six seconds
+        let start = Instant::now();
+        node.start_processing();
+        let messages = wait_for_messages(&node, 20).await;
+        
+        assert_eq!(messages.len(), 20);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+    
+    #[tokio::test]
+    async fn test_timed_strategy_releases_batch() {
+        use crate::mixnet::mixing::TimedMixing;
+        
+        let config = Arc::new(AetherConfig::default());
+        let node = MixNode::with_strategy(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9092".to_string(),
+            config,
+            Box::new(TimedMixing { batch_interval_ms: 200 }),
+        ).unwrap();
+        
+        let route = vec![PublicKey::from_bytes(&node.info.public_key_bytes).unwrap()];
+        for i in 0..3u8 {
+            node.process_packet(OutfoxPacket::new(&[i], &route).unwrap()).await.unwrap();
+        }
+        
+        // Nothing leaves before the interval, then the whole batch does
+        assert!(node.receive_message().await.is_none());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        node.flush_pool().await;
+        
+        let mut released = Vec::new();
+        while let Some(message) = node.receive_message().await {
+            released.push(message[0]);
+        }
+        released.sort();
+        assert_eq!(released, vec![0, 1, 2]);
+        assert_eq!(node.flush_history().await, vec![Flush::all(3)]);
+        
+        // An empty round is not recorded
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        node.flush_pool().await;
+        assert_eq!(node.flush_history().await, vec![Flush::all(3)]);
+    }
+    
+    #[tokio::test]
+    async fn test_cover_packet_loops_back_to_sender() {
+        let mut config = AetherConfig::default();
+        config.mixnet_layers = 2;
+        config.cover_traffic_ratio = 1.0;
+        let config = Arc::new(config);
+        let sender = MixNode::new(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9095".to_string(),
+            Arc::clone(&config),
+        ).unwrap();
+        let relay = MixNode::new(
+            2,
+            NodeRole::ExitGateway,
+            1000,
+            "127.0.0.1:9096".to_string(),
+            config,
+        ).unwrap();
+        sender.add_peer(&relay.info).await;
+        sender.start_processing();
+        relay.start_processing();
+        
+        sender.generate_cover_traffic().await.unwrap();
+        let cover = wait_for_outgoing(&sender).await.unwrap();
+        assert_eq!(cover.to_bytes().unwrap().len(), MAX_PACKET_SIZE);
+        
+        // The relay cannot tell it apart from real traffic and forwards it back
+        relay.process_packet(cover).await.unwrap();
+        let returning = wait_for_outgoing(&relay).await.unwrap();
+        sender.process_packet(returning).await.unwrap();
+        
+        let stats = sender.get_stats().await;
+        assert_eq!(stats.cover_packets_sent, 1);
+        assert_eq!(stats.cover_loops_returned, 1);
+        assert_eq!(stats.packets_processed, 0);
+        assert!(sender.receive_message().await.is_none());
+        assert_eq!(relay.get_stats().await.packets_processed, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_cover_reaches_wire_after_rotation() {
+        use tokio::io::AsyncReadExt;
+        
+        let mut config = AetherConfig::default();
+        config.mixnet_layers = 2;
+        config.cover_traffic_ratio = 1.0;
+        let config = Arc::new(config);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sender = MixNode::new(
+            1,
+            NodeRole::EntryGateway,
+            1000,
+            "127.0.0.1:9099".to_string(),
+            Arc::clone(&config),
+        ).unwrap();
+        let relay = MixNode::new(
+            2,
+            NodeRole::ExitGateway,
+            1000,
+            listener.local_addr().unwrap().to_string(),
+            Arc::clone(&config),
+        ).unwrap();
+        sender.add_peer(&relay.published_info()).await;
+        
+        // Both nodes rotate; the sender routes to the relay's announced next key
+        let schedule = EpochSchedule::from_config(&config.mixnet);
+        let next = relay.published_info().epoch_keys[1].epoch;
+        sender.advance_epoch(schedule.start(next));
+        relay.advance_epoch(schedule.start(next));
+        sender.start_processing();
+        relay.start_processing();
+        
+        sender.generate_cover_traffic().await.unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (cover, next_hop) = loop {
+            if let Some(queued) = sender.outgoing_queue.pop() {
+                break queued;
+            }
+            assert!(Instant::now() < deadline, "no cover packet was queued");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        sender.forward_packet(cover, next_hop).await;
+        
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut frame = vec![0u8; MAX_PACKET_SIZE];
+        stream.read_exact(&mut frame).await.unwrap();
+        relay.process_packet(OutfoxPacket::from_bytes(&frame).unwrap()).await.unwrap();
+        assert!(wait_for_outgoing(&relay).await.is_some());
+    }
 }
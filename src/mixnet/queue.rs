@@ -0,0 +1,213 @@
+//! Packet queues shared between a node's tasks
+//!
+//! Producers push without blocking; consumers either poll or wait until an
+//! item arrives, so idle workers sleep instead of spinning on a timer. A
+//! queue may be bounded, in which case its `DropPolicy` decides which
+//! packets are given up under pressure.
+
+use crate::config::DropPolicy;
+use parking_lot::Mutex;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Notify;
+
+/// A queued item, marked if the node generated it as cover traffic
+struct Entry<T> {
+    item: T,
+    cover: bool,
+}
+
+/// FIFO queue with async wake-up for consumers
+pub struct PacketQueue<T> {
+    items: Mutex<VecDeque<Entry<T>>>,
+    ready: Notify,
+    capacity: usize,
+    policy: DropPolicy,
+    dropped: AtomicU64,
+}
+
+impl<T> Default for PacketQueue<T> {
+    fn default() -> Self {
+        Self::bounded(usize::MAX, DropPolicy::DropNewest)
+    }
+}
+
+impl<T> PacketQueue<T> {
+    /// Create an empty, unbounded queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty queue holding at most `capacity` items
+    pub fn bounded(capacity: usize, policy: DropPolicy) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            capacity,
+            policy,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Append an item and wake one waiting consumer
+    ///
+    /// Returns `false` if the drop policy refused the item.
+    pub fn push(&self, item: T) -> bool {
+        self.enqueue(Entry { item, cover: false })
+    }
+
+    /// Append a cover item, which `DropPolicy::CoverFirst` gives up first
+    pub fn push_cover(&self, item: T) -> bool {
+        self.enqueue(Entry { item, cover: true })
+    }
+
+    fn enqueue(&self, entry: Entry<T>) -> bool {
+        let mut items = self.items.lock();
+
+        let admitted = match self.policy {
+            DropPolicy::DropNewest => items.len() < self.capacity,
+            DropPolicy::RandomEarly { min_fill } => {
+                let min = (self.capacity as f64 * min_fill.clamp(0.0, 1.0)) as usize;
+                if items.len() >= self.capacity {
+                    false
+                } else if items.len() < min {
+                    true
+                } else {
+                    let drop_probability = (items.len() - min + 1) as f64 / (self.capacity - min + 1) as f64;
+                    !rand::thread_rng().gen_bool(drop_probability)
+                }
+            }
+            DropPolicy::CoverFirst => {
+                if items.len() < self.capacity {
+                    true
+                } else if let Some(oldest_cover) = items.iter().position(|e| e.cover) {
+                    // Room is made by evicting cover, which counts as the drop
+                    items.remove(oldest_cover);
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+
+        if !admitted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        items.push_back(entry);
+        drop(items);
+        self.ready.notify_one();
+        true
+    }
+
+    /// Put an item taken by `pop` back at the front of the queue
+    ///
+    /// The item was already admitted once, so the drop policy is not
+    /// consulted again.
+    pub fn requeue(&self, item: T) {
+        self.items.lock().push_front(Entry { item, cover: false });
+        self.ready.notify_one();
+    }
+
+    /// Take the oldest item, if any
+    pub fn pop(&self) -> Option<T> {
+        self.items.lock().pop_front().map(|e| e.item)
+    }
+
+    /// Take the oldest item, waiting for one to arrive
+    pub async fn pop_wait(&self) -> T {
+        loop {
+            // Register interest before checking, so a push in between is not missed
+            let notified = self.ready.notified();
+            if let Some(item) = self.pop() {
+                return item;
+            }
+            notified.await;
+        }
+    }
+
+    /// Number of queued items
+    pub fn len(&self) -> usize {
+        self.items.lock().len()
+    }
+
+    /// Whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.items.lock().is_empty()
+    }
+
+    /// Items refused or evicted by the drop policy so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_pop_wait_wakes_on_push() {
+        let queue = Arc::new(PacketQueue::new());
+
+        let consumer = {
+            let queue = Arc::clone(&queue);
+            tokio::spawn(async move { queue.pop_wait().await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        queue.push(42u32);
+
+        let item = tokio::time::timeout(Duration::from_secs(1), consumer).await.unwrap().unwrap();
+        assert_eq!(item, 42);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_drop_newest_refuses_when_full() {
+        let queue = PacketQueue::bounded(2, DropPolicy::DropNewest);
+
+        assert!(queue.push(1u32));
+        assert!(queue.push(2));
+        assert!(!queue.push(3));
+
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_cover_first_evicts_oldest_cover() {
+        let queue = PacketQueue::bounded(3, DropPolicy::CoverFirst);
+
+        queue.push(1u32);
+        queue.push_cover(100);
+        queue.push_cover(101);
+        assert!(queue.push(2));
+        assert!(queue.push(3));
+
+        // Only real packets are left, so the next one is refused
+        assert!(!queue.push(4));
+        assert_eq!(queue.dropped(), 3);
+        let left: Vec<u32> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(left, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_random_early_drop_spares_short_queue() {
+        let queue = PacketQueue::bounded(100, DropPolicy::RandomEarly { min_fill: 0.5 });
+
+        for i in 0..50u32 {
+            assert!(queue.push(i));
+        }
+        let accepted = (0..1000u32).filter(|&i| queue.push(i)).count();
+
+        assert_eq!(queue.len(), 50 + accepted);
+        assert!(queue.len() <= 100);
+        assert_eq!(queue.dropped(), 1000 - accepted as u64);
+    }
+}
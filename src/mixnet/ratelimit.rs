@@ -0,0 +1,124 @@
+//! Per-peer rate limiting
+//!
+//! Each peer address gets a token bucket refilled at a fixed rate, so one
+//! flooding peer cannot crowd everyone else out of a node's queues.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Most peers tracked at once; past it the least recently seen are forgotten
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Peers forgotten at once when the table is full, so eviction's scan is
+/// paid once per batch of new peers rather than once per packet
+const EVICTION_BATCH: usize = MAX_TRACKED_PEERS / 10;
+
+/// Token bucket of one peer
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token-bucket rate limiter keyed by peer address
+pub struct PeerRateLimiter {
+    /// Tokens added per second; 0 disables limiting
+    rate: f64,
+    /// Most tokens a bucket holds
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    limited: AtomicU64,
+}
+
+impl PeerRateLimiter {
+    /// Allow `rate` packets per second per peer, with bursts of up to `burst`
+    pub fn new(rate: f64, burst: usize) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1) as f64,
+            buckets: Mutex::new(HashMap::new()),
+            limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Take a token for one packet from `peer`, returning whether it may pass
+    pub fn allow(&self, peer: IpAddr) -> bool {
+        if self.rate <= 0.0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+
+        if buckets.len() >= MAX_TRACKED_PEERS && !buckets.contains_key(&peer) {
+            // A peer that could have refilled its bucket is indistinguishable from a new one
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.refilled).as_secs_f64() * rate < burst);
+
+            // Otherwise make room by forgetting the peers seen least recently
+            if buckets.len() >= MAX_TRACKED_PEERS {
+                let mut seen: Vec<Instant> = buckets.values().map(|b| b.refilled).collect();
+                let excess = buckets.len() - MAX_TRACKED_PEERS + EVICTION_BATCH;
+                let (_, &mut cutoff, _) = seen.select_nth_unstable(excess - 1);
+                buckets.retain(|_, b| b.refilled > cutoff);
+            }
+        }
+
+        let bucket = buckets.entry(peer).or_insert(Bucket { tokens: self.burst, refilled: now });
+        let earned = now.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + earned).min(self.burst);
+        bucket.refilled = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            self.limited.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Packets refused so far
+    pub fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_limit_per_peer() {
+        let limiter = PeerRateLimiter::new(1.0, 3);
+        let flooder: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let passed = (0..10).filter(|_| limiter.allow(flooder)).count();
+        assert_eq!(passed, 3);
+        assert_eq!(limiter.limited(), 7);
+
+        // Another peer keeps its own allowance
+        assert!(limiter.allow(other));
+    }
+
+    #[test]
+    fn test_full_table_forgets_least_recently_seen() {
+        let limiter = PeerRateLimiter::new(1.0, 1);
+        let peer = |i: usize| IpAddr::from([10, (i >> 16) as u8, (i >> 8) as u8, i as u8]);
+
+        // Every bucket is drained, so none can be dropped as idle
+        for i in 0..MAX_TRACKED_PEERS {
+            assert!(limiter.allow(peer(i)));
+        }
+        assert!(!limiter.allow(peer(MAX_TRACKED_PEERS - 1)));
+
+        assert!(limiter.allow(peer(MAX_TRACKED_PEERS)));
+        let buckets = limiter.buckets.lock();
+        assert!(buckets.len() <= MAX_TRACKED_PEERS);
+        assert!(!buckets.contains_key(&peer(0)));
+        assert!(buckets.contains_key(&peer(MAX_TRACKED_PEERS - 1)));
+    }
+}
@@ -0,0 +1,403 @@
+//! Stratified topology
+//!
+//! Mixes are arranged in layers, and every path takes one mix from each
+//! layer in order. Once per epoch the topology manager decides which
+//! registered nodes are active and which layer each one serves in, keeping
+//! the layers' bandwidth balanced so none becomes the bottleneck.
+//!
+//! The only randomness comes from a hash of the epoch and the node set, so
+//! everyone holding the same registrations and reputations computes the
+//! same topology, and layer membership still changes from one epoch to the
+//! next. Reputations are local observations, though, so two parties'
+//! inputs rarely match exactly: only a `SignedTopology` from a trusted
+//! signer is authoritative, and clients route by it rather than by a
+//! topology they computed themselves.
+
+use crate::crypto::hybrid_pq::{HybridPublicKey, HybridSignature, HybridSigner};
+use crate::error::{AetherError, Result};
+use crate::mixnet::node::{NodeInfo, NodeRole};
+use crate::routing::ReputationSystem;
+use crate::MIXNET_LAYERS;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Domain separator for the per-epoch randomness
+const SEED_DOMAIN: &[u8] = b"aether-topology-seed-v1";
+
+/// Domain separator for topology signatures
+const SIGNATURE_DOMAIN: &[u8] = b"aether-topology-document-v1";
+
+/// Resolution at which reputation scales a node's selection weight
+const REPUTATION_SCALE: f64 = 1_000_000.0;
+
+/// A node offering to mix, with its advertised capacity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RegisteredNode {
+    pub info: NodeInfo,
+
+    /// Advertised bandwidth in packets per second
+    pub bandwidth: u64,
+}
+
+impl RegisteredNode {
+    /// Weight for selection into the active set: stake scaled by reputation
+    ///
+    /// Integer arithmetic keeps the draw identical on every platform.
+    fn selection_weight(&self) -> u128 {
+        let reputation = (self.info.reputation.clamp(0.0, 1.0) * REPUTATION_SCALE).round() as u128;
+        self.info.stake as u128 * reputation
+    }
+}
+
+/// Topology selection parameters
+#[derive(Clone, Debug)]
+pub struct TopologyConfig {
+    /// Number of mix layers
+    pub layers: usize,
+
+    /// Nodes below this reputation are left out
+    pub min_reputation: f64,
+
+    /// Nodes staking less than this are left out
+    pub min_stake: u64,
+
+    /// Most nodes active in an epoch, drawn by stake and reputation
+    /// (0 for every eligible node)
+    pub max_active: usize,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> Self {
+        Self {
+            layers: MIXNET_LAYERS,
+            min_reputation: 0.5,
+            min_stake: 0,
+            max_active: 0,
+        }
+    }
+}
+
+/// Layer assignment for one epoch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Topology {
+    pub epoch: u64,
+
+    /// Nodes of each layer, entry layer first and sorted by id; each node's
+    /// `info.layer` is the layer it was assigned (1-based)
+    pub layers: Vec<Vec<RegisteredNode>>,
+}
+
+impl Topology {
+    /// Layer (1-based) the node with `id` serves in this epoch
+    pub fn layer_of(&self, id: &[u8; 32]) -> Option<usize> {
+        self.layers
+            .iter()
+            .position(|layer| layer.iter().any(|node| &node.info.id == id))
+            .map(|index| index + 1)
+    }
+
+    /// Total advertised bandwidth of each layer
+    pub fn layer_bandwidth(&self) -> Vec<u64> {
+        self.layers.iter().map(|layer| layer.iter().map(|node| node.bandwidth).sum()).collect()
+    }
+
+    /// Whether every layer has at least one node, so paths can be built
+    pub fn is_complete(&self) -> bool {
+        !self.layers.is_empty() && self.layers.iter().all(|layer| !layer.is_empty())
+    }
+
+    /// Hash of the canonical encoding, which is what gets signed
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let encoded = bincode::serialize(self).map_err(|e| AetherError::Serialization(e.to_string()))?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(SIGNATURE_DOMAIN);
+        hasher.update(&encoded);
+        Ok(*hasher.finalize().as_bytes())
+    }
+}
+
+/// A topology signed by the party that computed it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedTopology {
+    /// The signed topology
+    pub topology: Topology,
+    /// Public key of the signer
+    pub signer: HybridPublicKey,
+    /// Signature over the topology's digest
+    pub signature: HybridSignature,
+}
+
+impl SignedTopology {
+    /// Sign `topology` with `signer`
+    pub fn sign(topology: Topology, signer: &HybridSigner) -> Result<Self> {
+        let signature = signer.sign(&topology.digest()?);
+        Ok(Self { topology, signer: signer.public_key(), signature })
+    }
+
+    /// Check that the document is intact and signed by `trusted`
+    pub fn verify(&self, trusted: &HybridPublicKey) -> Result<&Topology> {
+        if &self.signer != trusted {
+            return Err(AetherError::Crypto("Topology signed by an untrusted key".to_string()));
+        }
+        trusted
+            .verify(&self.topology.digest()?, &self.signature)
+            .map_err(AetherError::Crypto)?;
+        Ok(&self.topology)
+    }
+}
+
+/// Randomness derived from the epoch and the eligible node set
+struct EpochRng(blake3::OutputReader);
+
+impl EpochRng {
+    fn new(epoch: u64, nodes: &[&RegisteredNode]) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(SEED_DOMAIN);
+        hasher.update(&epoch.to_be_bytes());
+        for node in nodes {
+            hasher.update(&node.info.id);
+        }
+        Self(hasher.finalize_xof())
+    }
+
+    /// Uniform draw from `0..n`, without modulo bias
+    fn below(&mut self, n: u128) -> u128 {
+        let zone = u128::MAX - u128::MAX % n;
+        loop {
+            let mut bytes = [0u8; 16];
+            self.0.fill(&mut bytes);
+            let draw = u128::from_be_bytes(bytes);
+            if draw < zone {
+                return draw % n;
+            }
+        }
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        self.below(len as u128) as usize
+    }
+}
+
+/// Tracks registered nodes and assigns them to layers each epoch
+pub struct TopologyManager {
+    config: TopologyConfig,
+    registered: HashMap<[u8; 32], RegisteredNode>,
+    current: Option<Topology>,
+}
+
+impl TopologyManager {
+    /// Create a manager with no registered nodes
+    pub fn new(config: TopologyConfig) -> Self {
+        Self {
+            config,
+            registered: HashMap::new(),
+            current: None,
+        }
+    }
+
+    /// Register a node, replacing an earlier registration with the same id
+    pub fn register(&mut self, node: RegisteredNode) {
+        self.registered.insert(node.info.id, node);
+    }
+
+    /// Remove a node, returning whether it was registered
+    pub fn deregister(&mut self, id: &[u8; 32]) -> bool {
+        self.registered.remove(id).is_some()
+    }
+
+    /// Number of registered nodes
+    pub fn registered(&self) -> usize {
+        self.registered.len()
+    }
+
+    /// Take every registered node's reputation from `reputation`, where known
+    ///
+    /// The result reflects this party's own view of the network, so a
+    /// topology computed afterwards only binds others once it is signed.
+    pub fn apply_reputation(&mut self, reputation: &ReputationSystem) {
+        for node in self.registered.values_mut() {
+            if let Some(known) = reputation.get_reputation(&node.info.id) {
+                node.info.reputation = known.score;
+            }
+        }
+    }
+
+    /// Compute the topology of `epoch` from the current registrations
+    ///
+    /// Eligible nodes are drawn into the active set by stake and
+    /// reputation, then placed in an epoch-specific random order, each on
+    /// the layer with the least bandwidth so far. Every layer's bandwidth
+    /// therefore stays within one node's bandwidth of every other's.
+    pub fn compute(&self, epoch: u64) -> Topology {
+        let mut eligible: Vec<&RegisteredNode> = self
+            .registered
+            .values()
+            .filter(|node| {
+                node.info.role != NodeRole::Validator
+                    && node.info.reputation >= self.config.min_reputation
+                    && node.info.stake >= self.config.min_stake
+            })
+            .collect();
+        eligible.sort_by_key(|node| node.info.id);
+        let mut rng = EpochRng::new(epoch, &eligible);
+
+        // Weighted sampling without replacement
+        if self.config.max_active > 0 && eligible.len() > self.config.max_active {
+            let mut active = Vec::with_capacity(self.config.max_active);
+            while active.len() < self.config.max_active {
+                let total: u128 = eligible.iter().map(|node| node.selection_weight()).sum();
+                let chosen = if total == 0 {
+                    rng.index(eligible.len())
+                } else {
+                    let mut target = rng.below(total);
+                    eligible
+                        .iter()
+                        .position(|node| {
+                            let weight = node.selection_weight();
+                            if target < weight {
+                                return true;
+                            }
+                            target -= weight;
+                            false
+                        })
+                        .expect("draw is below the total weight")
+                };
+                active.push(eligible.remove(chosen));
+            }
+            eligible = active;
+        }
+
+        for i in (1..eligible.len()).rev() {
+            eligible.swap(i, rng.index(i + 1));
+        }
+
+        let layer_count = self.config.layers.max(1);
+        let mut layers: Vec<Vec<RegisteredNode>> = vec![Vec::new(); layer_count];
+        let mut load = vec![0u64; layer_count];
+        for node in eligible {
+            let lightest = *load.iter().min().expect("at least one layer");
+            let candidates: Vec<usize> = (0..layer_count).filter(|&layer| load[layer] == lightest).collect();
+            let layer = candidates[rng.index(candidates.len())];
+
+            // Nodes without advertised bandwidth still spread across layers
+            load[layer] += node.bandwidth.max(1);
+            let mut node = node.clone();
+            node.info.layer = layer + 1;
+            layers[layer].push(node);
+        }
+        for layer in &mut layers {
+            layer.sort_by_key(|node| node.info.id);
+        }
+
+        Topology { epoch, layers }
+    }
+
+    /// Recompute the topology for a new epoch and make it current
+    pub fn rebalance(&mut self, epoch: u64) -> &Topology {
+        let topology = self.compute(epoch);
+        if !topology.is_complete() {
+            tracing::warn!("Topology for epoch {} leaves a layer empty", epoch);
+        }
+        self.current.insert(topology)
+    }
+
+    /// The topology of the last rebalance, if any
+    pub fn current(&self) -> Option<&Topology> {
+        self.current.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(n: u8, bandwidth: u64) -> RegisteredNode {
+        RegisteredNode {
+            info: NodeInfo {
+                id: [n; 32],
+                layer: 0,
+                role: NodeRole::MixNode,
+                reputation: 1.0,
+                stake: 1000,
+                address: format!("10.0.0.{}:9000", n),
+                public_key_bytes: Vec::new(),
+                epoch_keys: Vec::new(),
+            },
+            bandwidth,
+        }
+    }
+
+    fn ids(topology: &Topology) -> Vec<Vec<[u8; 32]>> {
+        topology.layers.iter().map(|layer| layer.iter().map(|node| node.info.id).collect()).collect()
+    }
+
+    #[test]
+    fn test_same_registrations_give_same_balanced_topology() {
+        let nodes: Vec<RegisteredNode> = (1..=30).map(|n| node(n, 100 + 37 * n as u64)).collect();
+        let mut forward = TopologyManager::new(TopologyConfig::default());
+        let mut backward = TopologyManager::new(TopologyConfig::default());
+        for node in &nodes {
+            forward.register(node.clone());
+        }
+        for node in nodes.iter().rev() {
+            backward.register(node.clone());
+        }
+
+        let topology = forward.rebalance(7).clone();
+        assert_eq!(topology.digest().unwrap(), backward.compute(7).digest().unwrap());
+        assert!(topology.is_complete());
+        assert_eq!(topology.layers.len(), MIXNET_LAYERS);
+
+        let bandwidth = topology.layer_bandwidth();
+        let largest = nodes.iter().map(|node| node.bandwidth).max().unwrap();
+        assert!(bandwidth.iter().max().unwrap() - bandwidth.iter().min().unwrap() <= largest);
+        for (index, layer) in topology.layers.iter().enumerate() {
+            assert!(layer.iter().all(|node| node.info.layer == index + 1));
+        }
+
+        // A new epoch reshuffles the layers
+        assert_ne!(ids(&forward.compute(8)), ids(&topology));
+    }
+
+    #[test]
+    fn test_ineligible_nodes_left_out_and_active_set_capped() {
+        let config = TopologyConfig { max_active: 10, ..TopologyConfig::default() };
+        let mut manager = TopologyManager::new(config);
+        for n in 1..=20 {
+            manager.register(node(n, 100));
+        }
+        let mut validator = node(21, 100);
+        validator.info.role = NodeRole::Validator;
+        manager.register(validator);
+
+        // Reputation from loop monitoring pushes node 1 below the threshold
+        let mut reputation = ReputationSystem::new();
+        reputation.init_node([1; 32], 0.1);
+        manager.apply_reputation(&reputation);
+
+        let topology = manager.rebalance(3);
+        let active: usize = topology.layers.iter().map(Vec::len).sum();
+        assert_eq!(active, 10);
+        assert_eq!(topology.layer_of(&[1; 32]), None);
+        assert_eq!(topology.layer_of(&[21; 32]), None);
+        assert!(topology.layers.iter().all(|layer| layer.len() == 2));
+    }
+
+    #[test]
+    fn test_signed_topology_verification() {
+        let mut manager = TopologyManager::new(TopologyConfig::default());
+        for n in 1..=5 {
+            manager.register(node(n, 100));
+        }
+        let signer = HybridSigner::new();
+        let signed = SignedTopology::sign(manager.rebalance(1).clone(), &signer).unwrap();
+        assert_eq!(signed.verify(&signer.public_key()).unwrap().epoch, 1);
+
+        let other = HybridSigner::new();
+        assert!(signed.verify(&other.public_key()).is_err());
+
+        let mut tampered = signed.clone();
+        tampered.topology.layers.swap(0, 1);
+        assert!(tampered.verify(&signer.public_key()).is_err());
+    }
+}
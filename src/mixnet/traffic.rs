@@ -1,17 +1,71 @@
 //! Traffic shaping and cover traffic generation
 
-use crate::protocols::OutfoxPacket;
+use crate::protocols::{OutfoxPacket, MAX_MESSAGE_SIZE};
+use crate::crypto::hash::blake3_hash;
 use crate::crypto::kyber::PublicKey;
-use std::collections::VecDeque;
+use crate::error::{AetherError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
 use rand::Rng;
 
-/// Traffic shaping engine
+/// Most real packets waiting for a slot on one link
+const DEFAULT_MAX_BACKLOG: usize = 1000;
+
+/// Identifies a link by the id of the node at its far end
+pub type LinkId = [u8; 32];
+
+/// What an observer of one link has seen, and what it carried
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStats {
+    /// Ticks the link has been shaped for
+    pub ticks: u64,
+    
+    /// Real packets sent in slots
+    pub real_sent: u64,
+    
+    /// Cover packets sent in slots no real packet filled
+    pub cover_sent: u64,
+    
+    /// Real packets refused because the link's backlog was full
+    pub dropped: u64,
+}
+
+impl LinkStats {
+    /// Packets an observer of the link has seen
+    pub fn observed(&self) -> u64 {
+        self.real_sent + self.cover_sent
+    }
+}
+
+/// One shaped link
+struct Link {
+    /// Real packets waiting for a slot
+    backlog: VecDeque<OutfoxPacket>,
+    
+    /// Source of cover packets routed via this link's peer
+    cover: CoverTrafficGenerator,
+    
+    stats: LinkStats,
+}
+
+/// Constant-rate link shaper
+///
+/// Every tick, each link sends exactly `packets_per_tick` packets. Real
+/// packets queued for the link take slots first and cover packets fill the
+/// rest, so the timing and volume on a link do not depend on real load.
+/// Outfox packets are all the same length, so the slots look alike too.
 pub struct TrafficShaper {
-    /// Target traffic rate (packets per second)
-    target_rate: f64,
+    /// Packets sent on every link per tick
+    packets_per_tick: usize,
+    
+    /// Time between ticks
+    tick_interval: Duration,
     
-    /// Cover traffic ratio
-    cover_ratio: f64,
+    /// Most real packets waiting on one link
+    max_backlog: usize,
+    
+    links: HashMap<LinkId, Link>,
     
     /// Recent packet history for entropy calculation
     history: VecDeque<PacketTimestamp>,
@@ -24,34 +78,106 @@ struct PacketTimestamp {
 }
 
 impl TrafficShaper {
-    pub fn new(target_rate: f64, cover_ratio: f64) -> Self {
+    /// Shape every link to `packets_per_tick` packets each `tick_interval`
+    pub fn new(packets_per_tick: usize, tick_interval: Duration) -> Self {
         Self {
-            target_rate,
-            cover_ratio,
+            packets_per_tick,
+            tick_interval,
+            max_backlog: DEFAULT_MAX_BACKLOG,
+            links: HashMap::new(),
             history: VecDeque::new(),
         }
     }
     
-    /// Shape traffic by adding cover packets
-    pub fn shape(&mut self, real_packets: Vec<OutfoxPacket>) -> Vec<OutfoxPacket> {
-        let mut shaped = real_packets;
-        let num_real = shaped.len();
+    /// Time between ticks
+    pub fn tick_interval(&self) -> Duration {
+        self.tick_interval
+    }
+    
+    /// Packets per second on every link
+    pub fn link_rate(&self) -> f64 {
+        self.packets_per_tick as f64 / self.tick_interval.as_secs_f64()
+    }
+    
+    /// Shape the link to `peer`, filling idle slots with cover packets sent
+    /// over `cover_route`, which must start at `peer`
+    pub fn add_link(&mut self, peer: LinkId, cover_route: Vec<PublicKey>) -> Result<()> {
+        if cover_route.first().map(|hop| blake3_hash(hop.as_bytes())) != Some(peer) {
+            return Err(AetherError::Routing("Cover route must start at the link's peer".to_string()));
+        }
         
-        // Calculate how many cover packets to add
-        let num_cover = (num_real as f64 * self.cover_ratio / (1.0 - self.cover_ratio)) as usize;
+        let mut cover = CoverTrafficGenerator::new(self.link_rate());
+        cover.set_dummy_route(cover_route);
+        // Every idle slot depends on this route, so make sure it builds now
+        if cover.generate_cover_packet().is_none() {
+            return Err(AetherError::Routing("Cover route cannot carry a packet".to_string()));
+        }
+        self.links.insert(peer, Link { backlog: VecDeque::new(), cover, stats: LinkStats::default() });
+        Ok(())
+    }
+    
+    /// Stop shaping the link to `peer`, returning its unsent real packets
+    pub fn remove_link(&mut self, peer: &LinkId) -> Vec<OutfoxPacket> {
+        self.links.remove(peer).map(|link| link.backlog.into()).unwrap_or_default()
+    }
+    
+    /// Queue a real packet for the next free slot on the link to `peer`
+    ///
+    /// Returns `false` if the link's backlog is full and the packet was dropped.
+    pub fn enqueue(&mut self, peer: &LinkId, packet: OutfoxPacket) -> Result<bool> {
+        let link = self.links.get_mut(peer)
+            .ok_or_else(|| AetherError::Routing(format!("No shaped link to {}", hex::encode(&peer[..8]))))?;
         
-        // Add cover packets
-        for _ in 0..num_cover {
-            // Cover packets would be generated here
-            // For now, we just track the count
+        if link.backlog.len() >= self.max_backlog {
+            link.stats.dropped += 1;
+            return Ok(false);
         }
+        link.backlog.push_back(packet);
+        Ok(true)
+    }
+    
+    /// Produce one tick of output: exactly `packets_per_tick` packets per link
+    pub fn tick(&mut self) -> Result<Vec<(LinkId, OutfoxPacket)>> {
+        let mut output = Vec::with_capacity(self.links.len() * self.packets_per_tick);
+        let mut sent_cover = Vec::with_capacity(output.capacity());
         
-        // Shuffle to mix real and cover packets
-        use rand::seq::SliceRandom;
-        let mut rng = rand::thread_rng();
-        shaped.shuffle(&mut rng);
+        for (&peer, link) in self.links.iter_mut() {
+            for _ in 0..self.packets_per_tick {
+                let (packet, is_cover) = match link.backlog.pop_front() {
+                    Some(packet) => (packet, false),
+                    None => {
+                        let packet = link.cover.generate_cover_packet()
+                            .ok_or_else(|| AetherError::InvalidState("Failed to build cover packet".to_string()))?;
+                        (packet, true)
+                    }
+                };
+                
+                if is_cover {
+                    link.stats.cover_sent += 1;
+                } else {
+                    link.stats.real_sent += 1;
+                }
+                sent_cover.push(is_cover);
+                output.push((peer, packet));
+            }
+            link.stats.ticks += 1;
+        }
         
-        shaped
+        for is_cover in sent_cover {
+            self.record_packet(is_cover);
+        }
+        Ok(output)
+    }
+    
+    /// Counters of the link to `peer`
+    pub fn link_stats(&self, peer: &LinkId) -> Option<LinkStats> {
+        self.links.get(peer).map(|link| link.stats.clone())
+    }
+    
+    /// Whether every link has carried exactly `packets_per_tick` packets per
+    /// tick so far, whatever its real load
+    pub fn is_constant_rate(&self) -> bool {
+        self.links.values().all(|link| link.stats.observed() == link.stats.ticks * self.packets_per_tick as u64)
     }
     
     /// Calculate Shannon entropy of traffic patterns
@@ -129,7 +255,7 @@ impl CoverTrafficGenerator {
         
         // Generate random dummy data
         let mut rng = rand::thread_rng();
-        let size = rng.gen_range(100..2000);
+        let size = rng.gen_range(100..=MAX_MESSAGE_SIZE);
         let dummy_data: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
         
         // Create packet (would go back to self in a loop)
@@ -148,7 +274,7 @@ mod tests {
     
     #[test]
     fn test_traffic_shaper() {
-        let mut shaper = TrafficShaper::new(100.0, 0.4);
+        let mut shaper = TrafficShaper::new(4, Duration::from_millis(40));
         
         for i in 0..50 {
             shaper.record_packet(i % 5 == 0); // 20% cover traffic
@@ -158,4 +284,37 @@ mod tests {
         let entropy = shaper.calculate_entropy();
         assert!(entropy > 0.0);
     }
+    
+    #[test]
+    fn test_link_rate_independent_of_real_load() {
+        use crate::crypto::kyber::KeyPair;
+        
+        let busy = KeyPair::generate().public_key;
+        let idle = KeyPair::generate().public_key;
+        let busy_id = blake3_hash(busy.as_bytes());
+        let idle_id = blake3_hash(idle.as_bytes());
+        
+        let mut shaper = TrafficShaper::new(3, Duration::from_millis(100));
+        shaper.add_link(busy_id, vec![busy.clone()]).unwrap();
+        shaper.add_link(idle_id, vec![idle.clone()]).unwrap();
+        assert!(shaper.add_link([7u8; 32], vec![idle]).is_err());
+        
+        for i in 0..5u8 {
+            let packet = OutfoxPacket::new(&[i], &[busy.clone()]).unwrap();
+            assert!(shaper.enqueue(&busy_id, packet).unwrap());
+        }
+        
+        for _ in 0..3 {
+            let output = shaper.tick().unwrap();
+            assert_eq!(output.iter().filter(|(peer, _)| *peer == busy_id).count(), 3);
+            assert_eq!(output.iter().filter(|(peer, _)| *peer == idle_id).count(), 3);
+        }
+        
+        let busy_stats = shaper.link_stats(&busy_id).unwrap();
+        let idle_stats = shaper.link_stats(&idle_id).unwrap();
+        assert_eq!((busy_stats.real_sent, busy_stats.cover_sent), (5, 4));
+        assert_eq!((idle_stats.real_sent, idle_stats.cover_sent), (0, 9));
+        assert_eq!(busy_stats.observed(), idle_stats.observed());
+        assert!(shaper.is_constant_rate());
+    }
 }
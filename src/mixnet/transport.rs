@@ -0,0 +1,173 @@
+//! TCP transport between mix nodes
+//!
+//! Packets travel as back-to-back frames of exactly `MAX_PACKET_SIZE` bytes,
+//! so no length prefix is needed and every frame on a link looks alike.
+
+use crate::error::{AetherError, Result};
+use crate::mixnet::queue::PacketQueue;
+use crate::mixnet::ratelimit::PeerRateLimiter;
+use crate::protocols::OutfoxPacket;
+use crate::MAX_PACKET_SIZE;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// Bind a listener on a node's address
+pub async fn bind(address: &str) -> Result<TcpListener> {
+    TcpListener::bind(address)
+        .await
+        .map_err(|e| AetherError::Network(format!("Failed to bind {}: {}", address, e)))
+}
+
+/// Most inbound connections read at once; further ones are closed on accept
+const MAX_INBOUND_CONNECTIONS: usize = 256;
+
+/// Longest wait for the next frame before an inbound connection is closed
+///
+/// Longer than the outgoing timeouts, since a link may sit idle between
+/// packets; the sending side reconnects when it next has one.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Accept connections and queue the packets read from them, within each
+/// peer's rate limit, until `cancel` fires
+///
+/// At most `MAX_INBOUND_CONNECTIONS` are served at once. Returns once every
+/// connection it accepted is closed.
+pub async fn accept_loop(
+    listener: TcpListener,
+    incoming: Arc<PacketQueue<OutfoxPacket>>,
+    limiter: Arc<PeerRateLimiter>,
+    cancel: CancellationToken,
+) {
+    let mut readers = JoinSet::new();
+    let slots = Arc::new(Semaphore::new(MAX_INBOUND_CONNECTIONS));
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    let Ok(permit) = Arc::clone(&slots).try_acquire_owned() else {
+                        tracing::warn!("Refusing connection from {}: too many open", peer);
+                        continue;
+                    };
+                    tracing::debug!("Accepted connection from {}", peer);
+                    let reader = read_packets(
+                        stream,
+                        peer.ip(),
+                        Arc::clone(&incoming),
+                        Arc::clone(&limiter),
+                        cancel.clone(),
+                    );
+                    // The permit is held until the connection closes
+                    readers.spawn(async move {
+                        reader.await;
+                        drop(permit);
+                    });
+                }
+                Err(e) => tracing::warn!("Accept failed: {}", e),
+            },
+            // Reap finished readers so long-lived listeners don't accumulate them
+            Some(_) = readers.join_next() => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+
+    drop(listener);
+    while readers.join_next().await.is_some() {}
+}
+
+/// Read fixed-size frames from a stream until it closes, stalls for
+/// `READ_TIMEOUT`, or `cancel` fires
+async fn read_packets(
+    mut stream: TcpStream,
+    peer: IpAddr,
+    incoming: Arc<PacketQueue<OutfoxPacket>>,
+    limiter: Arc<PeerRateLimiter>,
+    cancel: CancellationToken,
+) {
+    let mut frame = vec![0u8; MAX_PACKET_SIZE];
+    loop {
+        tokio::select! {
+            read = timeout(READ_TIMEOUT, stream.read_exact(&mut frame)) => {
+                if !matches!(read, Ok(Ok(_))) {
+                    break;
+                }
+            }
+            // A frame cut off here was never accepted
+            _ = cancel.cancelled() => break,
+        }
+
+        // Frames over the limit are skipped undecoded; both counters live in the node's stats
+        if !limiter.allow(peer) {
+            continue;
+        }
+
+        // A bad frame is dropped on its own; fixed framing keeps the stream in sync
+        match OutfoxPacket::from_bytes(&frame) {
+            Ok(packet) => {
+                incoming.push(packet);
+            }
+            Err(e) => tracing::warn!("Dropping undecodable frame: {}", e),
+        }
+    }
+}
+
+/// Longest wait for a peer to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest wait for a peer to take one frame
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A peer's connection, locked on its own so a slow peer stalls no other
+type PeerStream = Arc<Mutex<Option<TcpStream>>>;
+
+/// Outgoing connections to other nodes, reused across packets
+#[derive(Default)]
+pub struct Connections {
+    /// Only held to look up or add a peer's slot, never across I/O
+    streams: parking_lot::Mutex<HashMap<String, PeerStream>>,
+}
+
+impl Connections {
+    /// Create an empty connection pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send a packet to `address`, connecting (or reconnecting once) as needed
+    pub async fn send(&self, address: &str, packet: &OutfoxPacket) -> Result<()> {
+        let frame = packet.to_bytes()?;
+        let peer = Arc::clone(self.streams.lock().entry(address.to_string()).or_default());
+        let mut slot = peer.lock().await;
+
+        if let Some(stream) = slot.as_mut() {
+            if write_frame(stream, address, &frame).await.is_ok() {
+                return Ok(());
+            }
+            *slot = None;
+        }
+
+        let mut stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| AetherError::Network(format!("Timed out connecting to {}", address)))?
+            .map_err(|e| AetherError::Network(format!("Failed to connect to {}: {}", address, e)))?;
+        stream.set_nodelay(true)?;
+        write_frame(&mut stream, address, &frame).await?;
+        *slot = Some(stream);
+        Ok(())
+    }
+}
+
+/// Write one frame, giving up on a peer that stops reading
+async fn write_frame(stream: &mut TcpStream, address: &str, frame: &[u8]) -> Result<()> {
+    timeout(WRITE_TIMEOUT, stream.write_all(frame))
+        .await
+        .map_err(|_| AetherError::Network(format!("Timed out writing to {}", address)))??;
+    Ok(())
+}
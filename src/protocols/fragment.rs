@@ -0,0 +1,721 @@
+//! Message fragmentation and reassembly
+//!
+//! Messages larger than one packet are split into numbered fragments, each
+//! authenticated with a key shared by sender and recipient and sent over its
+//! own route. The recipient collects fragments per message and reassembles
+//! once all have arrived, or reports which ones are still missing when the
+//! message times out.
+//!
+//! With a non-zero redundancy the data fragments are Reed–Solomon coded:
+//! they are split into groups of at most `MAX_GROUP_DATA`, and each group
+//! gets parity fragments so that any `k` of its `n` fragments rebuild it.
+
+use crate::config::MixnetConfig;
+use crate::crypto::hash::keyed_hash;
+use crate::crypto::kyber::PublicKey;
+use crate::error::{AetherError, PacketError, Result};
+use crate::protocols::packet::OutfoxPacket;
+use crate::protocols::sphinx::{SphinxBuilder, SphinxPacket};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+
+/// Fragment header: message id (16) || index (u32) || total (u32)
+/// || data fragments (u32) || message length (u32) || data length (u16)
+const HEADER_SIZE: usize = 16 + 4 + 4 + 4 + 4 + 2;
+
+/// Authentication tag size
+const TAG_SIZE: usize = 32;
+
+/// Bytes each fragment spends on framing
+pub const FRAGMENT_OVERHEAD: usize = HEADER_SIZE + TAG_SIZE;
+
+/// Upper bound on fragments per message, so a forged header cannot make the
+/// receiver reserve unbounded state
+pub const MAX_FRAGMENTS: u32 = 1 << 16;
+
+/// Highest supported redundancy (parity fragments per data fragment)
+pub const MAX_REDUNDANCY: f64 = 3.0;
+
+/// Most data fragments in one erasure-coded group
+const MAX_GROUP_DATA: u32 = 64;
+
+/// Most fragments in one erasure-coded group, leaving headroom under the
+/// 256-shard limit of GF(2^8) for uneven group sizes
+const MAX_GROUP_SHARDS: u32 = 254;
+
+/// Most recently completed messages remembered, so that fragments still in
+/// flight after reassembly cannot start the same message over
+const MAX_FINISHED: usize = 4096;
+
+/// One authenticated piece of a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fragment {
+    /// Random identifier shared by all fragments of a message
+    pub message_id: [u8; 16],
+
+    /// Position of this fragment (0-based)
+    pub index: u32,
+
+    /// Number of fragments in the message, parity included
+    pub total: u32,
+
+    /// Number of data fragments (equal to `total` without erasure coding)
+    pub data_total: u32,
+
+    /// Length of the whole message
+    pub message_len: u32,
+
+    /// Fragment contents
+    pub data: Vec<u8>,
+}
+
+impl Fragment {
+    /// Encode the fragment and append its authentication tag
+    pub fn to_bytes(&self, auth_key: &[u8; 32]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FRAGMENT_OVERHEAD + self.data.len());
+        bytes.extend_from_slice(&self.message_id);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(&self.data_total.to_be_bytes());
+        bytes.extend_from_slice(&self.message_len.to_be_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+
+        let tag = keyed_hash(auth_key, &bytes);
+        bytes.extend_from_slice(&tag);
+        bytes
+    }
+
+    /// Decode and authenticate a fragment
+    ///
+    /// Trailing bytes after the tag are ignored, since some carriers (Sphinx)
+    /// deliver their payload padded to a fixed size.
+    pub fn from_bytes(data: &[u8], auth_key: &[u8; 32]) -> Result<Self> {
+        if data.len() < FRAGMENT_OVERHEAD {
+            return Err(AetherError::Packet(PacketError::Malformed("Fragment too short".to_string())));
+        }
+
+        let len = u16::from_be_bytes([data[32], data[33]]) as usize;
+        let body_end = HEADER_SIZE + len;
+        if data.len() < body_end + TAG_SIZE {
+            return Err(AetherError::Packet(PacketError::Malformed("Fragment truncated".to_string())));
+        }
+
+        let expected = keyed_hash(auth_key, &data[..body_end]);
+        if !bool::from(expected[..].ct_eq(&data[body_end..body_end + TAG_SIZE])) {
+            return Err(AetherError::Packet(PacketError::AuthenticationFailed));
+        }
+
+        let mut message_id = [0u8; 16];
+        message_id.copy_from_slice(&data[..16]);
+        let index = u32::from_be_bytes(data[16..20].try_into().unwrap());
+        let total = u32::from_be_bytes(data[20..24].try_into().unwrap());
+        let data_total = u32::from_be_bytes(data[24..28].try_into().unwrap());
+        let message_len = u32::from_be_bytes(data[28..32].try_into().unwrap());
+
+        let max_parity = (data_total as f64 * MAX_REDUNDANCY).ceil() as u32;
+        if total == 0
+            || total > MAX_FRAGMENTS
+            || index >= total
+            || data_total == 0
+            || data_total > total
+            || total - data_total > max_parity
+        {
+            return Err(AetherError::Packet(PacketError::Malformed("Invalid fragment numbering".to_string())));
+        }
+
+        Ok(Self {
+            message_id,
+            index,
+            total,
+            data_total,
+            message_len,
+            data: data[HEADER_SIZE..body_end].to_vec(),
+        })
+    }
+}
+
+/// Split `message` into fragments whose encoding fits in `capacity` bytes
+///
+/// `redundancy` is the number of parity fragments per data fragment
+/// (`MixnetConfig::fec_redundancy`); zero sends plain fragments, so losing
+/// any one of them loses the message.
+pub fn fragment_message(message: &[u8], capacity: usize, redundancy: f64) -> Result<Vec<Fragment>> {
+    if capacity <= FRAGMENT_OVERHEAD {
+        return Err(AetherError::Packet(PacketError::Malformed(format!(
+            "Fragment capacity must exceed {} bytes",
+            FRAGMENT_OVERHEAD
+        ))));
+    }
+
+    if !(0.0..=MAX_REDUNDANCY).contains(&redundancy) {
+        return Err(AetherError::Packet(PacketError::Malformed(format!(
+            "Redundancy must be between 0 and {}",
+            MAX_REDUNDANCY
+        ))));
+    }
+
+    let message_len = u32::try_from(message.len())
+        .map_err(|_| AetherError::Packet(PacketError::Malformed("Message too large".to_string())))?;
+
+    let chunk_size = (capacity - FRAGMENT_OVERHEAD).min(u16::MAX as usize);
+    let data_total = message.len().div_ceil(chunk_size).max(1) as u32;
+    let parity_total = parity_fragments(data_total, redundancy);
+    let total = data_total + parity_total;
+    if total > MAX_FRAGMENTS {
+        return Err(AetherError::Packet(PacketError::Malformed(format!(
+            "Message needs more than {} fragments",
+            MAX_FRAGMENTS
+        ))));
+    }
+
+    let mut shards: Vec<Vec<u8>> = (0..data_total as usize)
+        .map(|i| {
+            let start = i * chunk_size;
+            let end = (start + chunk_size).min(message.len());
+            message[start..end].to_vec()
+        })
+        .collect();
+
+    if parity_total > 0 {
+        // Erasure coding needs equal-sized shards; padding is trimmed on
+        // reassembly using the message length
+        for shard in shards.iter_mut() {
+            shard.resize(chunk_size, 0);
+        }
+
+        let mut coded = Vec::with_capacity(total as usize);
+        let mut data = shards.into_iter();
+        for group in group_layout(data_total, parity_total) {
+            let mut group_shards: Vec<Vec<u8>> = data.by_ref().take(group.data as usize).collect();
+            group_shards.resize(group.shards() as usize, vec![0u8; chunk_size]);
+            group.codec()?.encode(&mut group_shards).map_err(erasure_error)?;
+            coded.extend(group_shards);
+        }
+        shards = coded;
+    }
+
+    let message_id: [u8; 16] = rand::random();
+    let fragments = shards
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| Fragment {
+            message_id,
+            index: i as u32,
+            total,
+            data_total,
+            message_len,
+            data,
+        })
+        .collect();
+
+    Ok(fragments)
+}
+
+/// Number of parity fragments for `data_total` data fragments
+///
+/// With any redundancy at all, every group gets at least one parity
+/// fragment: Reed–Solomon cannot code a group without parity.
+fn parity_fragments(data_total: u32, redundancy: f64) -> u32 {
+    let parity_total = (data_total as f64 * redundancy).ceil() as u32;
+    if parity_total == 0 {
+        return 0;
+    }
+    parity_total.max(data_total.div_ceil(MAX_GROUP_DATA))
+}
+
+/// One erasure-coded group of consecutive fragments
+#[derive(Clone, Copy, Debug)]
+struct Group {
+    /// Index of the group's first fragment
+    start: u32,
+    data: u32,
+    parity: u32,
+}
+
+impl Group {
+    fn shards(&self) -> u32 {
+        self.data + self.parity
+    }
+
+    fn codec(&self) -> Result<ReedSolomon> {
+        ReedSolomon::new(self.data as usize, self.parity as usize).map_err(erasure_error)
+    }
+}
+
+/// Split the fragments of a message into erasure-coded groups
+///
+/// Groups are laid out back to back, each holding its data fragments then
+/// its parity fragments. Sender and receiver derive the same layout from
+/// the counts in the fragment header.
+fn group_layout(data_total: u32, parity_total: u32) -> Vec<Group> {
+    if parity_total == 0 {
+        return vec![Group { start: 0, data: data_total, parity: 0 }];
+    }
+
+    let count = data_total
+        .div_ceil(MAX_GROUP_DATA)
+        .max((data_total + parity_total).div_ceil(MAX_GROUP_SHARDS));
+
+    let mut start = 0;
+    (0..count)
+        .map(|i| {
+            let group = Group {
+                start,
+                data: data_total / count + u32::from(i < data_total % count),
+                parity: parity_total / count + u32::from(i < parity_total % count),
+            };
+            start += group.shards();
+            group
+        })
+        .collect()
+}
+
+fn erasure_error(err: reed_solomon_erasure::Error) -> AetherError {
+    AetherError::Packet(PacketError::Malformed(format!("Erasure coding failed: {:?}", err)))
+}
+
+/// Fragment a message into Outfox packets, each on the route chosen for its index
+///
+/// Parity fragments are added as set by `config.fec_redundancy`.
+/// `select_route` should return an independently sampled route per call so
+/// that no single path carries the whole message.
+pub fn fragment_into_outfox(
+    message: &[u8],
+    auth_key: &[u8; 32],
+    config: &MixnetConfig,
+    mut select_route: impl FnMut(u32) -> Vec<PublicKey>,
+) -> Result<Vec<OutfoxPacket>> {
+    fragment_message(message, super::MAX_MESSAGE_SIZE, config.fec_redundancy)?
+        .iter()
+        .map(|fragment| OutfoxPacket::new(&fragment.to_bytes(auth_key), &select_route(fragment.index)))
+        .collect()
+}
+
+/// Fragment a message into Sphinx packets, each on the route chosen for its
+/// index, with parity fragments as set by `config.fec_redundancy`
+pub fn fragment_into_sphinx(
+    message: &[u8],
+    auth_key: &[u8; 32],
+    config: &MixnetConfig,
+    mut select_route: impl FnMut(u32) -> Vec<[u8; 32]>,
+) -> std::result::Result<Vec<SphinxPacket>, Box<dyn std::error::Error>> {
+    fragment_message(message, super::sphinx::MAX_MESSAGE_SIZE, config.fec_redundancy)?
+        .iter()
+        .map(|fragment| SphinxBuilder::new(select_route(fragment.index), fragment.to_bytes(auth_key)).build())
+        .collect()
+}
+
+/// A message that timed out before all its fragments arrived
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncompleteMessage {
+    /// Identifier of the message
+    pub message_id: [u8; 16],
+
+    /// Number of fragments the message was split into
+    pub total: u32,
+
+    /// Indices of the fragments that never arrived
+    ///
+    /// With erasure coding, only groups that could not be rebuilt are listed.
+    pub missing: Vec<u32>,
+}
+
+/// Fragments received so far for one message
+struct PartialMessage {
+    total: u32,
+    data_total: u32,
+    message_len: u32,
+    fragments: Vec<Option<Vec<u8>>>,
+    groups: Vec<Group>,
+    /// Fragments received per group
+    received: Vec<u32>,
+    /// Groups holding enough fragments to be rebuilt
+    groups_ready: usize,
+    first_seen: Instant,
+}
+
+impl PartialMessage {
+    fn new(fragment: &Fragment) -> Self {
+        let groups = group_layout(fragment.data_total, fragment.total - fragment.data_total);
+        Self {
+            total: fragment.total,
+            data_total: fragment.data_total,
+            message_len: fragment.message_len,
+            fragments: vec![None; fragment.total as usize],
+            received: vec![0; groups.len()],
+            groups,
+            groups_ready: 0,
+            first_seen: Instant::now(),
+        }
+    }
+
+    /// Store a fragment, returning whether every group can now be rebuilt
+    fn insert(&mut self, fragment: Fragment) -> bool {
+        let slot = &mut self.fragments[fragment.index as usize];
+        // Duplicates are harmless; keep the first copy
+        if slot.is_none() {
+            *slot = Some(fragment.data);
+
+            let g = self.groups.partition_point(|group| group.start <= fragment.index) - 1;
+            self.received[g] += 1;
+            if self.received[g] == self.groups[g].data {
+                self.groups_ready += 1;
+            }
+        }
+
+        self.groups_ready == self.groups.len()
+    }
+
+    /// Rebuild each group's data fragments and join them into the message
+    fn reassemble(self) -> Result<Vec<u8>> {
+        let mut fragments = self.fragments;
+        let mut message = Vec::with_capacity(self.message_len as usize);
+
+        for group in &self.groups {
+            let range = group.start as usize..(group.start + group.shards()) as usize;
+            let shards = &mut fragments[range];
+            if group.parity > 0 {
+                group.codec()?.reconstruct_data(shards).map_err(erasure_error)?;
+            }
+            for shard in shards[..group.data as usize].iter_mut() {
+                message.extend_from_slice(&shard.take().expect("data shard present"));
+            }
+        }
+
+        if message.len() < self.message_len as usize {
+            return Err(AetherError::Packet(PacketError::Malformed("Message length mismatch".to_string())));
+        }
+        message.truncate(self.message_len as usize);
+        Ok(message)
+    }
+
+    fn missing(&self) -> Vec<u32> {
+        self.groups
+            .iter()
+            .zip(&self.received)
+            .filter(|(group, &received)| received < group.data)
+            .flat_map(|(group, _)| group.start..group.start + group.shards())
+            .filter(|&i| self.fragments[i as usize].is_none())
+            .collect()
+    }
+}
+
+/// Collects fragments and reassembles complete messages
+pub struct Reassembler {
+    auth_key: [u8; 32],
+    timeout: Duration,
+    pending: HashMap<[u8; 16], PartialMessage>,
+    /// Messages already reassembled, oldest first, forgotten after `timeout`
+    finished: VecDeque<([u8; 16], Instant)>,
+    finished_ids: HashSet<[u8; 16]>,
+}
+
+impl Reassembler {
+    /// Create a reassembler that gives up on a message `timeout` after its first fragment
+    pub fn new(auth_key: [u8; 32], timeout: Duration) -> Self {
+        Self {
+            auth_key,
+            timeout,
+            pending: HashMap::new(),
+            finished: VecDeque::new(),
+            finished_ids: HashSet::new(),
+        }
+    }
+
+    /// Add a received fragment, returning the message once it is complete
+    pub fn insert(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let fragment = Fragment::from_bytes(data, &self.auth_key)?;
+
+        // Spare fragments of a message already delivered are dropped quietly
+        self.forget_finished();
+        let message_id = fragment.message_id;
+        if self.finished_ids.contains(&message_id) {
+            return Ok(None);
+        }
+
+        let partial = self
+            .pending
+            .entry(message_id)
+            .or_insert_with(|| PartialMessage::new(&fragment));
+
+        if partial.total != fragment.total
+            || partial.data_total != fragment.data_total
+            || partial.message_len != fragment.message_len
+        {
+            return Err(AetherError::Packet(PacketError::Malformed("Fragment count mismatch".to_string())));
+        }
+
+        if !partial.insert(fragment) {
+            return Ok(None);
+        }
+
+        let partial = self.pending.remove(&message_id).expect("entry exists");
+        self.finished.push_back((message_id, Instant::now()));
+        self.finished_ids.insert(message_id);
+        partial.reassemble().map(Some)
+    }
+
+    /// Forget completed messages older than the timeout, and the oldest
+    /// ones beyond `MAX_FINISHED`
+    fn forget_finished(&mut self) {
+        while let Some(&(id, finished_at)) = self.finished.front() {
+            if self.finished.len() < MAX_FINISHED && finished_at.elapsed() < self.timeout {
+                break;
+            }
+            self.finished.pop_front();
+            self.finished_ids.remove(&id);
+        }
+    }
+
+    /// Indices still missing for a pending message
+    pub fn missing(&self, message_id: &[u8; 16]) -> Option<Vec<u32>> {
+        self.pending.get(message_id).map(PartialMessage::missing)
+    }
+
+    /// Number of messages awaiting fragments
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drop messages older than the timeout and report what they were missing
+    pub fn expire(&mut self) -> Vec<IncompleteMessage> {
+        let timeout = self.timeout;
+        let expired: Vec<[u8; 16]> = self
+            .pending
+            .iter()
+            .filter(|(_, partial)| partial.first_seen.elapsed() >= timeout)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id).map(|partial| (id, partial)))
+            .map(|(message_id, partial)| IncompleteMessage {
+                message_id,
+                total: partial.total,
+                missing: partial.missing(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kyber::KeyPair;
+    use crate::protocols::packet::LayerOutcome;
+    use crate::protocols::sphinx::SphinxProcessor;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    fn large_message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip_out_of_order() {
+        let message = large_message(50_000);
+        let mut fragments = fragment_message(&message, 1000, 0.0).unwrap();
+        assert!(fragments.len() > 1);
+        fragments.reverse();
+
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut result = None;
+        for fragment in &fragments {
+            assert!(result.is_none());
+            result = reassembler.insert(&fragment.to_bytes(&KEY)).unwrap();
+        }
+
+        assert_eq!(result.unwrap(), message);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_forged_fragment_rejected() {
+        let fragments = fragment_message(b"hello", 100, 0.0).unwrap();
+        let mut bytes = fragments[0].to_bytes(&KEY);
+        bytes[FRAGMENT_OVERHEAD - TAG_SIZE] ^= 1;
+
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        assert!(reassembler.insert(&bytes).is_err());
+        assert!(reassembler.insert(&fragments[0].to_bytes(&[8u8; 32])).is_err());
+    }
+
+    #[test]
+    fn test_timeout_reports_missing() {
+        let fragments = fragment_message(&large_message(5000), 1000, 0.0).unwrap();
+        let id = fragments[0].message_id;
+
+        let mut reassembler = Reassembler::new(KEY, Duration::ZERO);
+        reassembler.insert(&fragments[0].to_bytes(&KEY)).unwrap();
+        reassembler.insert(&fragments[2].to_bytes(&KEY)).unwrap();
+
+        let expected: Vec<u32> = (0..fragments.len() as u32).filter(|i| *i != 0 && *i != 2).collect();
+        assert_eq!(reassembler.missing(&id).unwrap(), expected);
+
+        let expired = reassembler.expire();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].missing, expected);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_outfox_fragments_over_independent_routes() {
+        let nodes: Vec<_> = (0..6).map(|_| KeyPair::generate()).collect();
+        let message = large_message(5000);
+
+        // Each fragment takes a different pair of nodes
+        let mut routes = Vec::new();
+        let packets = fragment_into_outfox(&message, &KEY, &MixnetConfig::default(), |i| {
+            let route = vec![i as usize % 3, 3 + i as usize % 3];
+            routes.push(route.clone());
+            route.iter().map(|&n| nodes[n].public_key.clone()).collect()
+        })
+        .unwrap();
+        assert_eq!(packets.len(), 3);
+
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut result = None;
+        for (mut packet, route) in packets.into_iter().zip(routes) {
+            for &n in &route {
+                let processed = packet.process_layer(&nodes[n].secret_key).unwrap();
+                if let LayerOutcome::Final { message } = processed.outcome {
+                    result = reassembler.insert(&message).unwrap();
+                }
+            }
+        }
+
+        assert_eq!(result.unwrap(), message);
+    }
+
+    #[test]
+    fn test_sphinx_fragments() {
+        let secret = [3u8; 32];
+        let processor = SphinxProcessor::new(secret);
+        let message = large_message(4500);
+
+        let config = MixnetConfig::default();
+        let packets = fragment_into_sphinx(&message, &KEY, &config, |_| vec![processor.public_key()]).unwrap();
+        assert_eq!(packets.len(), 3);
+
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut result = None;
+        for packet in packets {
+            let processed = processor.process(packet).unwrap();
+            result = reassembler.insert(&processed.final_payload.unwrap()).unwrap();
+        }
+
+        assert_eq!(result.unwrap(), message);
+    }
+
+    #[test]
+    fn test_configured_redundancy_adds_parity() {
+        let processor = SphinxProcessor::new([3u8; 32]);
+        let message = large_message(4500);
+        let config = MixnetConfig { fec_redundancy: 1.0, ..MixnetConfig::default() };
+
+        let packets = fragment_into_sphinx(&message, &KEY, &config, |_| vec![processor.public_key()]).unwrap();
+        assert_eq!(packets.len(), 6);
+
+        // The parity stands in for the lost data fragments
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut delivered = Vec::new();
+        for packet in packets.into_iter().skip(3) {
+            let processed = processor.process(packet).unwrap();
+            delivered.extend(reassembler.insert(&processed.final_payload.unwrap()).unwrap());
+        }
+
+        assert_eq!(delivered, vec![message]);
+    }
+
+    #[test]
+    fn test_any_k_of_n_recovers() {
+        let message = large_message(30_000);
+        let fragments = fragment_message(&message, 1000, 0.5).unwrap();
+        let data_total = fragments[0].data_total as usize;
+        assert!(fragments.len() > data_total);
+
+        // Lose as many fragments as there is parity; the remaining k suffice
+        let lost = fragments.len() - data_total;
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut result = None;
+        for fragment in &fragments[lost..] {
+            assert!(result.is_none());
+            result = reassembler.insert(&fragment.to_bytes(&KEY)).unwrap();
+        }
+
+        assert_eq!(result.unwrap(), message);
+    }
+
+    #[test]
+    fn test_large_message_spans_groups() {
+        let message = large_message(400_000);
+        let mut fragments = fragment_message(&message, 1000, 0.5).unwrap();
+        let data_total = fragments[0].data_total;
+        assert!(group_layout(data_total, fragments[0].total - data_total).len() > 1);
+
+        // Drop one fragment in every five, spread across all groups
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut delivered = Vec::new();
+        for (i, fragment) in fragments.drain(..).enumerate() {
+            if i % 5 != 0 {
+                delivered.extend(reassembler.insert(&fragment.to_bytes(&KEY)).unwrap());
+            }
+        }
+
+        // Fragments arriving after reassembly neither deliver it again nor linger
+        assert_eq!(delivered, vec![message]);
+        assert_eq!(reassembler.pending(), 0);
+        assert!(reassembler.expire().is_empty());
+    }
+
+    #[test]
+    fn test_low_redundancy_gives_every_group_parity() {
+        let message = vec![1u8; 65 * 952];
+        let fragments = fragment_message(&message, 1000, 0.01).unwrap();
+        let data_total = fragments[0].data_total;
+        let groups = group_layout(data_total, fragments[0].total - data_total);
+        assert!(groups.len() > 1);
+        assert!(groups.iter().all(|g| g.parity >= 1));
+
+        // One lost fragment per group is still recoverable
+        let mut reassembler = Reassembler::new(KEY, Duration::from_secs(60));
+        let mut result = None;
+        for fragment in &fragments {
+            if !groups.iter().any(|g| g.start == fragment.index) {
+                result = reassembler.insert(&fragment.to_bytes(&KEY)).unwrap().or(result);
+            }
+        }
+
+        assert_eq!(result.unwrap(), message);
+    }
+
+    #[test]
+    fn test_too_few_fragments_reports_group() {
+        let fragments = fragment_message(&large_message(5000), 1000, 1.0).unwrap();
+        let data_total = fragments[0].data_total as usize;
+        let id = fragments[0].message_id;
+
+        let mut reassembler = Reassembler::new(KEY, Duration::ZERO);
+        for fragment in &fragments[..data_total - 1] {
+            assert!(reassembler.insert(&fragment.to_bytes(&KEY)).unwrap().is_none());
+        }
+
+        let expected: Vec<u32> = (data_total as u32 - 1..fragments.len() as u32).collect();
+        assert_eq!(reassembler.missing(&id).unwrap(), expected);
+        assert_eq!(reassembler.expire()[0].missing, expected);
+    }
+
+    #[test]
+    fn test_group_layout_bounds() {
+        for (data, parity) in [(1, 3), (64, 192), (65, 1), (1000, 3000), (40_000, 10_000)] {
+            let groups = group_layout(data, parity);
+            assert_eq!(groups.iter().map(|g| g.data).sum::<u32>(), data);
+            assert_eq!(groups.iter().map(|g| g.parity).sum::<u32>(), parity);
+            assert!(groups.iter().all(|g| g.data >= 1 && g.data <= MAX_GROUP_DATA && g.shards() <= 256));
+        }
+    }
+}
@@ -3,18 +3,51 @@
 pub mod packet;
 pub mod sphinx_compat;
 pub mod sphinx;
+pub mod replay;
+pub mod fragment;
 
-pub use packet::{OutfoxPacket, PacketMetadata, create_packet, process_packet_layer};
-pub use sphinx::{SphinxBuilder, SphinxProcessor, ProcessedPacket};
+pub use packet::{OutfoxPacket, PacketMetadata, LayerOutcome, ProcessedLayer, create_packet, process_packet_layer};
+pub use sphinx::{SphinxBuilder, SphinxProcessor, ProcessedPacket, Surb, SurbKeys};
+pub use replay::{ReplayCache, replay_tag};
+pub use fragment::{Fragment, IncompleteMessage, Reassembler, fragment_message, fragment_into_outfox, fragment_into_sphinx};
 
 /// Maximum packet size for Outfox (larger than Sphinx to accommodate post-quantum)
+///
+/// Every Outfox packet is exactly this size on the wire, whatever its path
+/// length or position on the path.
 pub const MAX_PACKET_SIZE: usize = 10000;
 
-/// Header size for Outfox packets
-pub const HEADER_SIZE: usize = 1568 * 5; // 5 layers of Kyber-1024 ciphertexts (7840 bytes)
+/// Maximum number of hops on an Outfox route
+pub const MAX_HOPS: usize = 5;
 
-/// Metadata size
-pub const METADATA_SIZE: usize = 128;
+/// Kyber-1024 ciphertext size
+pub const CIPHERTEXT_SIZE: usize = 1568;
 
-/// Payload size
-pub const PAYLOAD_SIZE: usize = MAX_PACKET_SIZE - HEADER_SIZE - METADATA_SIZE; // 2032 bytes
+/// Per-hop header MAC size
+pub const MAC_SIZE: usize = 32;
+
+/// Routing metadata carried for each hop inside the encrypted routing block
+/// (flags, next hop hash); it tells a hop nothing about its position
+pub const METADATA_SIZE: usize = 33;
+
+/// Space one hop consumes in the routing block: its metadata plus the next
+/// hop's MAC and KEM ciphertext
+pub const SLOT_SIZE: usize = METADATA_SIZE + MAC_SIZE + CIPHERTEXT_SIZE;
+
+/// Encrypted routing block size (6565 bytes)
+pub const BETA_SIZE: usize = (MAX_HOPS - 1) * SLOT_SIZE + METADATA_SIZE;
+
+/// Header size for Outfox packets: ciphertext, MAC and routing block (8165 bytes)
+pub const HEADER_SIZE: usize = CIPHERTEXT_SIZE + MAC_SIZE + BETA_SIZE;
+
+/// Wire format version written at the start of every packet
+pub const WIRE_VERSION: u8 = 1;
+
+/// Version and cipher-suite bytes preceding the header
+pub const PREAMBLE_SIZE: usize = 2;
+
+/// Payload size (1833 bytes)
+pub const PAYLOAD_SIZE: usize = MAX_PACKET_SIZE - PREAMBLE_SIZE - HEADER_SIZE;
+
+/// Largest message that fits in one packet, after the AEAD tag and length prefix
+pub const MAX_MESSAGE_SIZE: usize = PAYLOAD_SIZE - 16 - 2;
@@ -1,42 +1,83 @@
 //! Outfox packet implementation
+//!
+//! Every packet is exactly `MAX_PACKET_SIZE` bytes on the wire: a two-byte
+//! preamble (version, cipher suite), a header of `HEADER_SIZE` bytes and a
+//! payload of `PAYLOAD_SIZE` bytes.
+//!
+//! The header is `ct || gamma || beta`: the current hop's Kyber ciphertext,
+//! a MAC over `beta` keyed by that hop's KEM secret, and the encrypted
+//! routing block. Each hop decrypts `beta`, reads its `PacketMetadata` and
+//! the next hop's MAC and ciphertext from the first slot, then shifts the
+//! block left by one slot, Sphinx-style, so the header never shrinks and
+//! nothing in the clear reveals the hop's position or the path length.
 
 use serde::{Deserialize, Serialize};
-use crate::crypto::kyber::{PublicKey, SecretKey, encapsulate, decapsulate};
-use crate::crypto::symmetric::{encrypt_aead, generate_nonce};
-use crate::crypto::hash::{blake3_hash, derive_key};
-use crate::error::{AetherError, Result};
-use std::time::{SystemTime, UNIX_EPOCH};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use crate::crypto::kyber::{Ciphertext, PublicKey, SecretKey, ciphertext_size, encapsulate, decapsulate};
+use crate::crypto::lioness::{lioness_decrypt, lioness_encrypt};
+use crate::crypto::symmetric::{decrypt_aead, encrypt_aead};
+use crate::crypto::hash::{blake3_hash, derive_key, keyed_hash};
+use crate::protocols::replay::replay_tag;
+use crate::crypto::agility::CipherSuite;
+use crate::error::{AetherError, PacketError, Result};
+use rand::Rng;
+use subtle::ConstantTimeEq;
+use super::{BETA_SIZE, CIPHERTEXT_SIZE, HEADER_SIZE, MAC_SIZE, MAX_HOPS, MAX_MESSAGE_SIZE, MAX_PACKET_SIZE, METADATA_SIZE, PAYLOAD_SIZE, PREAMBLE_SIZE, SLOT_SIZE, WIRE_VERSION};
+
+/// Size of the AEAD tag on the innermost payload layer
+const AEAD_TAG_SIZE: usize = 16;
+
+/// Every hop key is used for exactly one encryption, so a fixed nonce is safe
+const LAYER_NONCE: [u8; 24] = [0u8; 24];
+
+/// Cipher suite of this construction: Kyber-1024 KEM per hop,
+/// XChaCha20-Poly1305 on the payload and ChaCha20/Lioness layers
+pub const CIPHER_SUITE: CipherSuite = CipherSuite::Kyber1024XChaCha20;
 
 /// Outfox packet structure
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OutfoxPacket {
-    /// Layered encrypted headers (one per hop)
+    /// Current hop's KEM ciphertext, MAC and encrypted routing block (`HEADER_SIZE` bytes)
     pub header: Vec<u8>,
-    
-    /// Encrypted payload
+
+    /// Encrypted payload, one Lioness layer per remaining hop (`PAYLOAD_SIZE` bytes)
     pub payload: Vec<u8>,
-    
-    /// Packet metadata
-    pub metadata: PacketMetadata,
 }
 
-/// Packet metadata
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Routing metadata for one hop
+///
+/// Only ever travels inside the encrypted routing block, so it is visible
+/// to the hop it was built for and to no one else.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PacketMetadata {
-    /// Timestamp of packet creation (nanoseconds since epoch)
-    pub timestamp: u64,
-    
-    /// Current layer being processed
-    pub layer: u8,
-    
-    /// Hash of next hop public key
+    /// Whether this hop is the exit
+    pub is_final: bool,
+
+    /// Hash of next hop public key (zero at the exit)
     pub next_hop_hash: [u8; 32],
-    
-    /// Integrity tag
-    pub integrity_tag: [u8; 32],
-    
-    /// Packet ID for tracking (does not reveal identity)
-    pub packet_id: [u8; 16],
+}
+
+impl PacketMetadata {
+    fn to_bytes(&self) -> [u8; METADATA_SIZE] {
+        let mut bytes = [0u8; METADATA_SIZE];
+        bytes[0] = self.is_final as u8;
+        bytes[1..33].copy_from_slice(&self.next_hop_hash);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < METADATA_SIZE || bytes[0] > 1 {
+            return Err(AetherError::Packet(PacketError::Malformed("Invalid routing metadata".to_string())));
+        }
+
+        let mut next_hop_hash = [0u8; 32];
+        next_hop_hash.copy_from_slice(&bytes[1..33]);
+        Ok(Self {
+            is_final: bytes[0] == 1,
+            next_hop_hash,
+        })
+    }
 }
 
 /// Route information for creating a packet
@@ -44,6 +85,60 @@ pub struct Route {
     pub hops: Vec<PublicKey>,
 }
 
+/// Outcome of peeling one layer at a mix node
+#[derive(Debug)]
+pub enum LayerOutcome {
+    /// The packet now holds the next layer; send it to the node whose
+    /// public key hashes to `next_hop_hash`
+    Forward {
+        /// BLAKE3 hash of the next hop's public key
+        next_hop_hash: [u8; 32],
+    },
+
+    /// This node is the exit and recovered the plaintext
+    Final {
+        /// Decrypted message
+        message: Vec<u8>,
+    },
+}
+
+/// Result of processing one layer
+#[derive(Debug)]
+pub struct ProcessedLayer {
+    /// Replay tag of this layer for the node's `ReplayCache`
+    pub replay_tag: [u8; 32],
+
+    /// Routing metadata decrypted at this hop
+    pub metadata: PacketMetadata,
+
+    /// What the node should do next
+    pub outcome: LayerOutcome,
+}
+
+/// Per-hop keys derived from the KEM shared secret
+struct HopKeys {
+    /// Stream cipher key for the routing block
+    routing: [u8; 32],
+    /// MAC key for the routing block
+    mac: [u8; 32],
+    /// Lioness key for this hop's payload layer
+    payload: [u8; 32],
+    /// AEAD key for the end-to-end payload (used only at the exit)
+    exit: [u8; 32],
+}
+
+impl HopKeys {
+    fn derive(shared_secret: &[u8]) -> Result<Self> {
+        let okm = derive_key(shared_secret, None, b"outfox-hop-keys", 128)?;
+        let mut keys = [[0u8; 32]; 4];
+        for (key, chunk) in keys.iter_mut().zip(okm.chunks_exact(32)) {
+            key.copy_from_slice(chunk);
+        }
+        let [routing, mac, payload, exit] = keys;
+        Ok(Self { routing, mac, payload, exit })
+    }
+}
+
 impl OutfoxPacket {
     /// Create a new Outfox packet for the given route
     pub fn new(
@@ -51,152 +146,228 @@ impl OutfoxPacket {
         route: &[PublicKey],
     ) -> Result<Self> {
         if route.is_empty() {
-            return Err(AetherError::Packet("Route cannot be empty".to_string()));
-        }
-        
-        if route.len() > 5 {
-            return Err(AetherError::Packet("Maximum 5 hops allowed".to_string()));
-        }
-        
-        // Generate packet ID
-        let mut packet_id = [0u8; 16];
-        use ring::rand::{SecureRandom, SystemRandom};
-        let rng = SystemRandom::new();
-        rng.fill(&mut packet_id)
-            .map_err(|_| AetherError::Crypto("Failed to generate packet ID".to_string()))?;
-        
-        // Build header by encapsulating for each hop
-        let mut header = Vec::new();
-        let mut shared_secrets = Vec::new();
-        
+            return Err(AetherError::Packet(PacketError::Malformed("Route cannot be empty".to_string())));
+        }
+
+        if route.len() > MAX_HOPS {
+            return Err(AetherError::Packet(PacketError::Malformed(format!("Maximum {} hops allowed", MAX_HOPS))));
+        }
+
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(AetherError::Packet(PacketError::Malformed(format!(
+                "Message exceeds {} bytes",
+                MAX_MESSAGE_SIZE
+            ))));
+        }
+
+        // Encapsulate a fresh secret for each hop
+        let mut ciphertexts = Vec::with_capacity(route.len());
+        let mut hop_keys = Vec::with_capacity(route.len());
         for pk in route.iter() {
             let (ct, ss) = encapsulate(pk);
-            header.extend_from_slice(ct.as_bytes());
-            shared_secrets.push(ss);
-        }
-        
-        // Pad header to fixed size (5 layers)
-        while header.len() < super::HEADER_SIZE {
-            header.push(0);
-        }
-        
-        // Encrypt payload with the last shared secret
-        let final_ss = &shared_secrets[shared_secrets.len() - 1];
-        let payload_key = derive_key(
-            final_ss.as_bytes(),
-            None,
-            b"payload-encryption",
-            32,
-        )?;
-        
-        let nonce = generate_nonce();
-        let mut payload_key_array = [0u8; 32];
-        payload_key_array.copy_from_slice(&payload_key[..32]);
-        
-        let encrypted_payload = encrypt_aead(
-            &payload_key_array,
-            &nonce,
-            message,
-            &packet_id,
-        )?;
-        
-        // Combine nonce and ciphertext
-        let mut payload = nonce.to_vec();
-        payload.extend_from_slice(&encrypted_payload);
-        
-        // Calculate next hop hash
-        let next_hop_hash = blake3_hash(route[0].as_bytes());
-        
-        // Calculate integrity tag
-        let integrity_tag = Self::calculate_integrity_tag(&header, &payload, &packet_id);
-        
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|_| AetherError::InvalidState("System time before epoch".to_string()))?
-            .as_nanos() as u64;
-        
-        Ok(Self {
-            header,
-            payload,
-            metadata: PacketMetadata {
-                timestamp,
-                layer: 0,
-                next_hop_hash,
-                integrity_tag,
-                packet_id,
-            },
-        })
+            ciphertexts.push(ct);
+            hop_keys.push(HopKeys::derive(ss.as_bytes())?);
+        }
+
+        // Tail that the hops' zero-shifting will reproduce, so later MACs verify
+        let filler = compute_filler(&hop_keys);
+        let last = route.len() - 1;
+
+        // Exit's routing block: its metadata, then random padding so the exit
+        // cannot tell where the filler starts
+        let mut beta = vec![0u8; BETA_SIZE];
+        rand::thread_rng().fill(&mut beta[METADATA_SIZE..]);
+        let exit_metadata = PacketMetadata {
+            is_final: true,
+            next_hop_hash: [0u8; 32],
+        };
+        beta[..METADATA_SIZE].copy_from_slice(&exit_metadata.to_bytes());
+        xor_into(&mut beta, &keystream(&hop_keys[last].routing, BETA_SIZE));
+        beta[BETA_SIZE - filler.len()..].copy_from_slice(&filler);
+        let mut gamma = keyed_hash(&hop_keys[last].mac, &beta);
+
+        // Wrap the remaining hops from the inside out. Each hop's first slot
+        // carries its metadata plus the next hop's MAC and ciphertext.
+        for i in (0..last).rev() {
+            let metadata = PacketMetadata {
+                is_final: false,
+                next_hop_hash: blake3_hash(route[i + 1].as_bytes()),
+            };
+
+            let mut block = Vec::with_capacity(BETA_SIZE);
+            block.extend_from_slice(&metadata.to_bytes());
+            block.extend_from_slice(&gamma);
+            block.extend_from_slice(ciphertexts[i + 1].as_bytes());
+            block.extend_from_slice(&beta[..BETA_SIZE - SLOT_SIZE]);
+
+            xor_into(&mut block, &keystream(&hop_keys[i].routing, BETA_SIZE));
+            gamma = keyed_hash(&hop_keys[i].mac, &block);
+            beta = block;
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(ciphertexts[0].as_bytes());
+        header.extend_from_slice(&gamma);
+        header.extend_from_slice(&beta);
+
+        // Encrypt the length-prefixed message end-to-end for the exit, then
+        // add one Lioness layer per hop
+        let mut plaintext = Vec::with_capacity(PAYLOAD_SIZE - AEAD_TAG_SIZE);
+        plaintext.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        plaintext.extend_from_slice(message);
+        plaintext.resize(PAYLOAD_SIZE - AEAD_TAG_SIZE, 0);
+
+        let mut payload = encrypt_aead(&hop_keys[last].exit, &LAYER_NONCE, &plaintext, &[])?;
+        for keys in hop_keys.iter().rev() {
+            lioness_encrypt(&keys.payload, &mut payload)?;
+        }
+
+        Ok(Self { header, payload })
     }
-    
+
     /// Process one layer of the packet at a mix node
-    pub fn process_layer(&mut self, secret_key: &SecretKey) -> Result<()> {
-        if self.metadata.layer >= 5 {
-            return Err(AetherError::Packet("Maximum layers exceeded".to_string()));
-        }
-        
-        // Extract ciphertext for this layer
-        let ct_size = 1568; // Kyber-1024 ciphertext size
-        let start = self.metadata.layer as usize * ct_size;
-        let end = start + ct_size;
-        
-        if end > self.header.len() {
-            return Err(AetherError::Packet("Invalid header size".to_string()));
-        }
-        
-        let ct_bytes = &self.header[start..end];
-        let ct = crate::crypto::kyber::Ciphertext::from_bytes(ct_bytes)?;
-        
+    ///
+    /// On `Forward` the packet is replaced by its next layer.
+    pub fn process_layer(&mut self, secret_key: &SecretKey) -> Result<ProcessedLayer> {
+        self.check_sizes()?;
+
+        let ct_size = ciphertext_size();
+        let ct = Ciphertext::from_bytes(&self.header[..ct_size])?;
+        let gamma = &self.header[ct_size..ct_size + MAC_SIZE];
+        let beta = &self.header[ct_size + MAC_SIZE..];
+
         // Decapsulate to get shared secret
-        let _shared_secret = decapsulate(&ct, secret_key)?;
-        
-        // Re-randomize this layer (important for unlinkability)
-        // Generate new ciphertext for next hop
-        let next_layer = self.metadata.layer + 1;
-        if (next_layer as usize) < 5 {
-            // Derive next hop's public key hash from routing info
-            // (In a real implementation, this would be embedded in the header)
-            self.metadata.layer = next_layer;
-        }
-        
-        // Update integrity tag
-        self.metadata.integrity_tag = Self::calculate_integrity_tag(
-            &self.header,
-            &self.payload,
-            &self.metadata.packet_id,
-        );
-        
-        Ok(())
+        let shared_secret = decapsulate(&ct, secret_key)?;
+        let replay_tag = replay_tag(shared_secret.as_bytes());
+        let keys = HopKeys::derive(shared_secret.as_bytes())?;
+
+        // Verify the routing block before decrypting anything
+        let expected_mac = keyed_hash(&keys.mac, beta);
+        if !bool::from(expected_mac[..].ct_eq(gamma)) {
+            return Err(AetherError::Packet(PacketError::AuthenticationFailed));
+        }
+
+        // Decrypt the routing block, shifting in one slot at the tail
+        let mut block = beta.to_vec();
+        block.resize(BETA_SIZE + SLOT_SIZE, 0);
+        xor_into(&mut block, &keystream(&keys.routing, BETA_SIZE + SLOT_SIZE));
+        let metadata = PacketMetadata::from_bytes(&block[..METADATA_SIZE])?;
+
+        lioness_decrypt(&keys.payload, &mut self.payload)?;
+
+        let outcome = if metadata.is_final {
+            let plaintext = decrypt_aead(&keys.exit, &LAYER_NONCE, &self.payload, &[])
+                .map_err(|_| AetherError::Packet(PacketError::AuthenticationFailed))?;
+
+            let len = u16::from_be_bytes([plaintext[0], plaintext[1]]) as usize;
+            if len > MAX_MESSAGE_SIZE {
+                return Err(AetherError::Packet(PacketError::Malformed("Invalid message length".to_string())));
+            }
+
+            LayerOutcome::Final { message: plaintext[2..2 + len].to_vec() }
+        } else {
+            // Next header: the ciphertext and MAC from our slot, then the shifted block
+            let gamma_start = METADATA_SIZE;
+            let ct_start = gamma_start + MAC_SIZE;
+
+            let mut header = Vec::with_capacity(HEADER_SIZE);
+            header.extend_from_slice(&block[ct_start..ct_start + CIPHERTEXT_SIZE]);
+            header.extend_from_slice(&block[gamma_start..ct_start]);
+            header.extend_from_slice(&block[SLOT_SIZE..]);
+            self.header = header;
+
+            LayerOutcome::Forward { next_hop_hash: metadata.next_hop_hash }
+        };
+
+        Ok(ProcessedLayer { replay_tag, metadata, outcome })
     }
-    
-    /// Calculate integrity tag for the packet
-    fn calculate_integrity_tag(header: &[u8], payload: &[u8], packet_id: &[u8; 16]) -> [u8; 32] {
-        let mut data = Vec::new();
-        data.extend_from_slice(header);
-        data.extend_from_slice(payload);
-        data.extend_from_slice(packet_id);
-        blake3_hash(&data)
-    }
-    
-    /// Verify integrity of the packet
-    pub fn verify_integrity(&self) -> bool {
-        let calculated_tag = Self::calculate_integrity_tag(
-            &self.header,
-            &self.payload,
-            &self.metadata.packet_id,
-        );
-        calculated_tag == self.metadata.integrity_tag
-    }
-    
-    /// Serialize packet to bytes
+
+    /// Encode the packet in the wire format
+    ///
+    /// Layout (see `docs/PACKET_FORMAT.md`): version (1) || cipher suite (1)
+    /// || header (`HEADER_SIZE`) || payload (`PAYLOAD_SIZE`), always exactly
+    /// `MAX_PACKET_SIZE` bytes.
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
-        bincode::serialize(self)
-            .map_err(|e| AetherError::Serialization(e.to_string()))
+        self.check_sizes()?;
+
+        let mut bytes = Vec::with_capacity(MAX_PACKET_SIZE);
+        bytes.push(WIRE_VERSION);
+        bytes.push(CIPHER_SUITE.id());
+        bytes.extend_from_slice(&self.header);
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
     }
-    
-    /// Deserialize packet from bytes
+
+    /// Decode a packet from the wire format
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
-        bincode::deserialize(data)
-            .map_err(|e| AetherError::Serialization(e.to_string()))
+        if data.len() != MAX_PACKET_SIZE {
+            return Err(AetherError::Packet(PacketError::InvalidLength {
+                expected: MAX_PACKET_SIZE,
+                actual: data.len(),
+            }));
+        }
+
+        if data[0] != WIRE_VERSION {
+            return Err(AetherError::Packet(PacketError::UnsupportedVersion(data[0])));
+        }
+
+        // Only accept the suite this construction implements; other known
+        // suites get their own packet format
+        if CipherSuite::from_id(data[1]) != Some(CIPHER_SUITE) {
+            return Err(AetherError::Packet(PacketError::UnsupportedCipherSuite(data[1])));
+        }
+
+        let body = &data[PREAMBLE_SIZE..];
+        Ok(Self {
+            header: body[..HEADER_SIZE].to_vec(),
+            payload: body[HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    fn check_sizes(&self) -> Result<()> {
+        if self.header.len() != HEADER_SIZE {
+            return Err(AetherError::Packet(PacketError::InvalidLength {
+                expected: HEADER_SIZE,
+                actual: self.header.len(),
+            }));
+        }
+        if self.payload.len() != PAYLOAD_SIZE {
+            return Err(AetherError::Packet(PacketError::InvalidLength {
+                expected: PAYLOAD_SIZE,
+                actual: self.payload.len(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// Compute the filler appended to the exit's routing block
+///
+/// Each hop shifts the block left by one slot and appends decrypted zeros;
+/// the filler is exactly those bytes, so the sender can MAC what later hops see.
+fn compute_filler(hop_keys: &[HopKeys]) -> Vec<u8> {
+    let mut filler = Vec::new();
+    for keys in &hop_keys[..hop_keys.len() - 1] {
+        filler.extend_from_slice(&[0u8; SLOT_SIZE]);
+        let stream = keystream(&keys.routing, BETA_SIZE + SLOT_SIZE);
+        let offset = stream.len() - filler.len();
+        xor_into(&mut filler, &stream[offset..]);
+    }
+    filler
+}
+
+/// ChaCha20 keystream of `len` bytes for a single-use key
+fn keystream(key: &[u8; 32], len: usize) -> Vec<u8> {
+    let mut stream = vec![0u8; len];
+    let mut cipher = ChaCha20::new(key.into(), &[0u8; 12].into());
+    cipher.apply_keystream(&mut stream);
+    stream
+}
+
+/// XOR `stream` into `data`
+fn xor_into(data: &mut [u8], stream: &[u8]) {
+    for (byte, k) in data.iter_mut().zip(stream) {
+        *byte ^= k;
     }
 }
 
@@ -206,7 +377,7 @@ pub fn create_packet(message: &[u8], route: &[PublicKey]) -> Result<OutfoxPacket
 }
 
 /// Process one layer of a packet (convenience function)
-pub fn process_packet_layer(packet: &mut OutfoxPacket, secret_key: &SecretKey) -> Result<()> {
+pub fn process_packet_layer(packet: &mut OutfoxPacket, secret_key: &SecretKey) -> Result<ProcessedLayer> {
     packet.process_layer(secret_key)
 }
 
@@ -214,41 +385,151 @@ pub fn process_packet_layer(packet: &mut OutfoxPacket, secret_key: &SecretKey) -
 mod tests {
     use super::*;
     use crate::crypto::kyber::KeyPair;
-    
+
+    fn route_of(keys: &[KeyPair]) -> Vec<PublicKey> {
+        keys.iter().map(|kp| kp.public_key.clone()).collect()
+    }
+
     #[test]
     fn test_packet_creation() {
         let route: Vec<_> = (0..3).map(|_| KeyPair::generate().public_key).collect();
         let message = b"Test message";
-        
+
         let packet = OutfoxPacket::new(message, &route).unwrap();
-        assert_eq!(packet.metadata.layer, 0);
-        assert!(packet.verify_integrity());
+        assert_eq!(packet.header.len(), HEADER_SIZE);
+        assert_eq!(packet.payload.len(), PAYLOAD_SIZE);
     }
-    
+
     #[test]
     fn test_packet_processing() {
         let keys: Vec<_> = (0..3).map(|_| KeyPair::generate()).collect();
-        let route: Vec<_> = keys.iter().map(|kp| kp.public_key.clone()).collect();
+        let route = route_of(&keys);
         let message = b"Secret message";
-        
+
         let mut packet = OutfoxPacket::new(message, &route).unwrap();
-        
+
         // Process through first hop
+        let processed = packet.process_layer(&keys[0].secret_key).unwrap();
+        assert!(!processed.metadata.is_final);
+        assert_eq!(packet.header.len(), HEADER_SIZE);
+        assert_eq!(packet.payload.len(), PAYLOAD_SIZE);
+    }
+
+    #[test]
+    fn test_five_hop_round_trip() {
+        let keys: Vec<_> = (0..5).map(|_| KeyPair::generate()).collect();
+        let route = route_of(&keys);
+        let message = b"Five hops through the mixnet";
+
+        let mut packet = OutfoxPacket::new(message, &route).unwrap();
+
+        for (i, kp) in keys.iter().enumerate() {
+            let processed = packet.process_layer(&kp.secret_key).unwrap();
+            match processed.outcome {
+                LayerOutcome::Forward { next_hop_hash } => {
+                    assert!(i < 4);
+                    assert_eq!(next_hop_hash, blake3_hash(route[i + 1].as_bytes()));
+                }
+                LayerOutcome::Final { message: received } => {
+                    assert_eq!(i, 4);
+                    assert_eq!(received, message);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_layers_unlinkable() {
+        let keys: Vec<_> = (0..3).map(|_| KeyPair::generate()).collect();
+        let route = route_of(&keys);
+
+        let mut packet = OutfoxPacket::new(b"msg", &route).unwrap();
+        let before = packet.clone();
         packet.process_layer(&keys[0].secret_key).unwrap();
-        assert_eq!(packet.metadata.layer, 1);
-        assert!(packet.verify_integrity());
+
+        // Neither header nor payload survives a hop unchanged
+        assert!(!before.header.windows(64).any(|w| packet.header.starts_with(w)));
+        assert!(!before.payload.windows(32).any(|w| packet.payload.starts_with(w)));
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let keys: Vec<_> = (0..3).map(|_| KeyPair::generate()).collect();
+
+        let mut packet = OutfoxPacket::new(b"msg", &route_of(&keys)).unwrap();
+        assert!(packet.process_layer(&keys[1].secret_key).is_err());
+    }
+
+    #[test]
+    fn test_constant_length_hides_path() {
+        let keys: Vec<_> = (0..5).map(|_| KeyPair::generate()).collect();
+
+        // Packets for every path length and at every hop look the same size
+        for hops in 1..=5 {
+            let mut packet = OutfoxPacket::new(b"msg", &route_of(&keys[..hops])).unwrap();
+            for kp in &keys[..hops] {
+                assert_eq!(packet.to_bytes().unwrap().len(), MAX_PACKET_SIZE);
+                packet.process_layer(&kp.secret_key).unwrap();
+            }
+        }
     }
-    
+
+    #[test]
+    fn test_tampered_header_rejected() {
+        let keys: Vec<_> = (0..3).map(|_| KeyPair::generate()).collect();
+        let mut packet = OutfoxPacket::new(b"msg", &route_of(&keys)).unwrap();
+        packet.process_layer(&keys[0].secret_key).unwrap();
+
+        packet.header[HEADER_SIZE - 1] ^= 1;
+        assert!(packet.process_layer(&keys[1].secret_key).is_err());
+    }
+
+    #[test]
+    fn test_tampered_payload_rejected() {
+        let keys: Vec<_> = (0..2).map(|_| KeyPair::generate()).collect();
+        let mut packet = OutfoxPacket::new(b"msg", &route_of(&keys)).unwrap();
+
+        packet.payload[0] ^= 1;
+        packet.process_layer(&keys[0].secret_key).unwrap();
+        assert!(packet.process_layer(&keys[1].secret_key).is_err());
+    }
+
     #[test]
     fn test_serialization() {
         let route: Vec<_> = (0..2).map(|_| KeyPair::generate().public_key).collect();
         let message = b"Data";
-        
+
         let packet = OutfoxPacket::new(message, &route).unwrap();
         let bytes = packet.to_bytes().unwrap();
         let decoded = OutfoxPacket::from_bytes(&bytes).unwrap();
-        
-        assert_eq!(packet.metadata.packet_id, decoded.metadata.packet_id);
-        assert_eq!(packet.metadata.layer, decoded.metadata.layer);
+
+        assert_eq!(bytes[0], WIRE_VERSION);
+        assert_eq!(bytes[1], CIPHER_SUITE.id());
+        assert_eq!(packet.header, decoded.header);
+        assert_eq!(packet.payload, decoded.payload);
+    }
+
+    #[test]
+    fn test_malformed_input_rejected() {
+        let route: Vec<_> = (0..2).map(|_| KeyPair::generate().public_key).collect();
+        let bytes = OutfoxPacket::new(b"Data", &route).unwrap().to_bytes().unwrap();
+
+        let err = OutfoxPacket::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            AetherError::Packet(PacketError::InvalidLength { expected: MAX_PACKET_SIZE, .. })
+        ));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = WIRE_VERSION + 1;
+        let err = OutfoxPacket::from_bytes(&wrong_version).unwrap_err();
+        assert!(matches!(err, AetherError::Packet(PacketError::UnsupportedVersion(_))));
+
+        for id in [0, CipherSuite::Kyber1024Aes256Gcm.id(), CipherSuite::Dilithium5ChaCha20.id(), 0xff] {
+            let mut wrong_suite = bytes.clone();
+            wrong_suite[1] = id;
+            let err = OutfoxPacket::from_bytes(&wrong_suite).unwrap_err();
+            assert!(matches!(err, AetherError::Packet(PacketError::UnsupportedCipherSuite(got)) if got == id));
+        }
     }
 }
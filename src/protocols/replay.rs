@@ -0,0 +1,258 @@
+//! Replay protection for processed packets
+//!
+//! Every packet a node decrypts yields a tag derived from its per-hop shared
+//! secret. Seeing the same tag twice means the packet was replayed, which an
+//! adversary could use to trace it through the network.
+
+use crate::error::{AetherError, Result};
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Magic bytes at the start of a persisted replay cache
+const FILE_MAGIC: &[u8; 4] = b"ARC1";
+
+/// Derive the replay tag for a per-hop shared secret
+pub fn replay_tag(shared_secret: &[u8]) -> [u8; 32] {
+    blake3::derive_key("aether replay tag v1", shared_secret)
+}
+
+/// Bounded store of replay tags, rotated with key epochs
+///
+/// Tags from the current and the previous epoch are remembered, since the
+/// previous epoch's key stays valid during the rotation grace window.
+/// Once an epoch's budget is spent, further packets are refused until the
+/// next rotation: forgetting old tags would let a flood open a replay window.
+pub struct ReplayCache {
+    state: Mutex<ReplayState>,
+    /// Maximum number of tags kept per epoch
+    capacity: usize,
+    /// Where to persist the cache, if anywhere
+    path: Option<PathBuf>,
+}
+
+struct ReplayState {
+    epoch: u64,
+    current: HashSet<[u8; 32]>,
+    previous: HashSet<[u8; 32]>,
+}
+
+impl ReplayCache {
+    /// Create an in-memory cache
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(ReplayState {
+                epoch: 0,
+                current: HashSet::new(),
+                previous: HashSet::new(),
+            }),
+            capacity,
+            path: None,
+        }
+    }
+
+    /// Create a cache persisted at `path`, loading existing tags if present
+    pub fn with_persistence(capacity: usize, path: impl AsRef<Path>) -> Result<Self> {
+        let mut cache = Self::new(capacity);
+        cache.path = Some(path.as_ref().to_path_buf());
+
+        if path.as_ref().exists() {
+            let data = fs::read(path.as_ref())?;
+            cache.decode(&data)?;
+        }
+
+        Ok(cache)
+    }
+
+    /// Record a tag, returning `false` if it was already seen or the
+    /// epoch's budget is spent
+    pub fn check_and_insert(&self, tag: [u8; 32]) -> bool {
+        let mut state = self.state.lock();
+
+        if state.current.contains(&tag) || state.previous.contains(&tag) {
+            return false;
+        }
+        if state.current.len() >= self.capacity {
+            return false;
+        }
+
+        state.current.insert(tag);
+        true
+    }
+
+    /// Whether the current epoch's budget is spent
+    pub fn is_full(&self) -> bool {
+        self.state.lock().current.len() >= self.capacity
+    }
+
+    /// Start a new key epoch, forgetting tags older than the previous one
+    pub fn rotate(&self, epoch: u64) {
+        let mut state = self.state.lock();
+        state.previous = std::mem::take(&mut state.current);
+        state.epoch = epoch;
+    }
+
+    /// Current key epoch
+    pub fn epoch(&self) -> u64 {
+        self.state.lock().epoch
+    }
+
+    /// Number of tags remembered across both epochs
+    pub fn len(&self) -> usize {
+        let state = self.state.lock();
+        state.current.len() + state.previous.len()
+    }
+
+    /// Whether the cache holds no tags
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the cache to its persistence path, if one is configured
+    pub fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // Write then rename so a crash never leaves a truncated file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.encode())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Layout: magic, epoch (u64 BE), then for the current and previous
+    /// epoch a tag count (u32 BE) followed by the 32-byte tags
+    fn encode(&self) -> Vec<u8> {
+        let state = self.state.lock();
+        let mut bytes = Vec::with_capacity(20 + 32 * (state.current.len() + state.previous.len()));
+        bytes.extend_from_slice(FILE_MAGIC);
+        bytes.extend_from_slice(&state.epoch.to_be_bytes());
+
+        bytes.extend_from_slice(&(state.current.len() as u32).to_be_bytes());
+        for tag in &state.current {
+            bytes.extend_from_slice(tag);
+        }
+        bytes.extend_from_slice(&(state.previous.len() as u32).to_be_bytes());
+        for tag in &state.previous {
+            bytes.extend_from_slice(tag);
+        }
+        bytes
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<()> {
+        let invalid = || AetherError::Serialization("Invalid replay cache file".to_string());
+
+        if data.len() < 12 || &data[..4] != FILE_MAGIC {
+            return Err(invalid());
+        }
+        let epoch = u64::from_be_bytes(data[4..12].try_into().map_err(|_| invalid())?);
+
+        let capacity = self.capacity;
+        let mut offset = 12;
+        let mut read_tags = || -> Result<Vec<[u8; 32]>> {
+            let count_bytes = data.get(offset..offset + 4).ok_or_else(invalid)?;
+            let count = u32::from_be_bytes(count_bytes.try_into().map_err(|_| invalid())?) as usize;
+            offset += 4;
+
+            // Same bound as at runtime; dropping tags instead would reopen replays
+            if count > capacity {
+                return Err(AetherError::Serialization(
+                    "Replay cache file exceeds configured capacity".to_string(),
+                ));
+            }
+
+            let end = offset + count * 32;
+            let tags = data.get(offset..end).ok_or_else(invalid)?;
+            offset = end;
+            Ok(tags.chunks_exact(32).map(|t| t.try_into().unwrap()).collect())
+        };
+        let current = read_tags()?;
+        let previous = read_tags()?;
+
+        let state = self.state.get_mut();
+        state.epoch = epoch;
+        state.current = current.into_iter().collect();
+        state.previous = previous.into_iter().collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_rejected() {
+        let cache = ReplayCache::new(16);
+        let tag = replay_tag(b"shared secret");
+
+        assert!(cache.check_and_insert(tag));
+        assert!(!cache.check_and_insert(tag));
+    }
+
+    #[test]
+    fn test_bounded_capacity() {
+        let cache = ReplayCache::new(4);
+        for i in 0..10u8 {
+            assert_eq!(cache.check_and_insert([i; 32]), i < 4);
+        }
+        assert_eq!(cache.len(), 4);
+        assert!(cache.is_full());
+
+        // Nothing was evicted: early tags are still refused
+        assert!(!cache.check_and_insert([0u8; 32]));
+
+        // A rotation frees the budget while keeping the old tags
+        cache.rotate(1);
+        assert!(cache.check_and_insert([9u8; 32]));
+        assert!(!cache.check_and_insert([0u8; 32]));
+    }
+
+    #[test]
+    fn test_rotation_keeps_one_previous_epoch() {
+        let cache = ReplayCache::new(16);
+        cache.check_and_insert([1u8; 32]);
+
+        cache.rotate(1);
+        assert!(!cache.check_and_insert([1u8; 32]));
+
+        cache.rotate(2);
+        assert_eq!(cache.epoch(), 2);
+        assert!(cache.check_and_insert([1u8; 32]));
+    }
+
+    #[test]
+    fn test_persistence_round_trip() {
+        let path = std::env::temp_dir().join(format!("aether-replay-{}.bin", rand::random::<u64>()));
+
+        let cache = ReplayCache::with_persistence(16, &path).unwrap();
+        cache.check_and_insert([1u8; 32]);
+        cache.rotate(7);
+        cache.check_and_insert([2u8; 32]);
+        cache.persist().unwrap();
+
+        let restored = ReplayCache::with_persistence(16, &path).unwrap();
+        assert_eq!(restored.epoch(), 7);
+        assert!(!restored.check_and_insert([1u8; 32]));
+        assert!(!restored.check_and_insert([2u8; 32]));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_file_rejected() {
+        let path = std::env::temp_dir().join(format!("aether-replay-{}.bin", rand::random::<u64>()));
+
+        let cache = ReplayCache::with_persistence(16, &path).unwrap();
+        for i in 0..8u8 {
+            cache.check_and_insert([i; 32]);
+        }
+        cache.persist().unwrap();
+
+        assert!(ReplayCache::with_persistence(4, &path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -4,14 +4,40 @@
 //! Used by Nym, Katzenpost, and proposed for Tor
 
 use blake3::Hasher;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use crate::crypto::lioness::{lioness_decrypt, lioness_encrypt};
+use crate::protocols::replay::{replay_tag, ReplayCache};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use curve25519_dalek::scalar::Scalar;
 use rand::Rng;
 use std::convert::TryInto;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
 
-const SECURITY_PARAMETER: usize = 16; // 128-bit security
-const MAX_HOPS: usize = 5;
+const SECURITY_PARAMETER: usize = 16; // 128-bit security (zero prefix checked at the final hop)
+/// Longest path: five mix layers plus the recipient, or the sender a loop returns to
+pub const MAX_HOPS: usize = 6;
 const ROUTING_INFO_SIZE: usize = 128;
 const PAYLOAD_SIZE: usize = 2048;
 const MAC_SIZE: usize = 32;
+const BETA_SIZE: usize = ROUTING_INFO_SIZE * MAX_HOPS;
+/// Largest message that fits in one packet after the zero integrity prefix
+pub const MAX_MESSAGE_SIZE: usize = PAYLOAD_SIZE - SECURITY_PARAMETER;
+/// Offset of the next hop's MAC inside a routing slot (after the 37-byte `RoutingInfo`)
+const GAMMA_OFFSET: usize = 37;
+
+// Routing flags
+const FLAG_RELAY: u8 = 0;
+const FLAG_FINAL: u8 = 1;
+const FLAG_REPLY: u8 = 2;
+
+// Key derivation contexts for per-hop subkeys
+const KEY_ROUTING: &str = "aether sphinx v1 routing";
+const KEY_PAYLOAD: &str = "aether sphinx v1 payload";
+const KEY_MAC: &str = "aether sphinx v1 mac";
+const KEY_BLINDING: &str = "aether sphinx v1 blinding";
 
 /// Sphinx packet header with layered encryption
 #[derive(Clone, Debug)]
@@ -20,7 +46,7 @@ pub struct SphinxHeader {
     pub alpha: [u8; 32],
     /// Routing information (encrypted for each hop)
     pub beta: Vec<u8>,
-    /// MAC for integrity
+    /// MAC over `beta`, keyed for the hop that receives this header
     pub gamma: [u8; MAC_SIZE],
 }
 
@@ -29,7 +55,7 @@ pub struct SphinxHeader {
 pub struct SphinxPacket {
     /// Header with routing
     pub header: SphinxHeader,
-    /// Encrypted payload
+    /// Payload under one Lioness layer per remaining hop
     pub payload: Vec<u8>,
 }
 
@@ -70,7 +96,7 @@ impl RoutingInfo {
 
 /// Sphinx packet builder
 pub struct SphinxBuilder {
-    /// Path through the network
+    /// Path through the network (X25519 public key of each hop)
     path: Vec<[u8; 32]>,
     /// Message to send
     message: Vec<u8>,
@@ -84,186 +110,463 @@ impl SphinxBuilder {
 
     /// Build the Sphinx packet with layered encryption
     pub fn build(&self) -> Result<SphinxPacket, Box<dyn std::error::Error>> {
-        let mut rng = rand::thread_rng();
-        
-        // Generate session key for this packet
-        let mut session_key = [0u8; 32];
-        rng.fill(&mut session_key);
-
-        // Create initial group element (public key)
-        let alpha = session_key; // Simplified - in real impl, use EC point multiplication
-
-        // Build routing info for each hop
-        let mut routing_infos = Vec::new();
-        for (i, hop) in self.path.iter().enumerate() {
-            routing_infos.push(RoutingInfo {
-                next_hop: *hop,
-                delay: 50 + (i as u32 * 10), // Increasing delays
-                flags: if i == self.path.len() - 1 { 1 } else { 0 }, // Final hop flag
-            });
-        }
-
-        // Encrypt routing info in reverse (onion layers)
-        let mut beta = vec![0u8; ROUTING_INFO_SIZE * MAX_HOPS];
-        for (i, info) in routing_infos.iter().rev().enumerate() {
-            let info_bytes = info.to_bytes();
-            let start = i * ROUTING_INFO_SIZE;
-            beta[start..start + info_bytes.len()].copy_from_slice(&info_bytes);
-            
-            // Encrypt this layer
-            let key = self.derive_key(&session_key, i as u8);
-            self.encrypt_layer(&mut beta, &key)?;
-        }
+        // The final hop is the destination itself
+        let destination = *self.path.last().ok_or("Path cannot be empty")?;
+        let (header, shared_secrets) = build_header(&self.path, destination, FLAG_FINAL)?;
 
-        // Encrypt payload
-        let mut encrypted_payload = self.message.clone();
-        encrypted_payload.resize(PAYLOAD_SIZE, 0); // Pad to fixed size
-        
+        // Encrypt payload with one Lioness layer per hop. The zero prefix
+        // lets the final hop detect tampering anywhere along the path.
+        let mut encrypted_payload = pad_payload(&self.message)?;
         for i in (0..self.path.len()).rev() {
-            let key = self.derive_key(&session_key, i as u8);
-            self.encrypt_layer(&mut encrypted_payload, &key)?;
+            let key = derive_hop_key(&shared_secrets[i], KEY_PAYLOAD);
+            lioness_encrypt(&key, &mut encrypted_payload)?;
         }
 
-        // Compute MAC
-        let mut hasher = Hasher::new();
-        hasher.update(&alpha);
-        hasher.update(&beta);
-        hasher.update(&encrypted_payload);
-        let hash = hasher.finalize();
-        let gamma: [u8; MAC_SIZE] = hash.as_bytes()[0..MAC_SIZE].try_into()?;
-
         Ok(SphinxPacket {
+            header,
+            payload: encrypted_payload,
+        })
+    }
+
+    /// Create a single-use reply block routed over `path`
+    ///
+    /// The last hop of `path` must be the creator's own Sphinx key. The
+    /// returned `Surb` is handed to the recipient (e.g. inside a payload via
+    /// `Surb::to_bytes`); the `SurbKeys` stay with the creator to read the reply.
+    pub fn build_surb(path: &[[u8; 32]]) -> Result<(Surb, SurbKeys), Box<dyn std::error::Error>> {
+        let first_hop = *path.first().ok_or("Path cannot be empty")?;
+
+        let mut id = [0u8; 32];
+        rand::thread_rng().fill(&mut id);
+        let (header, shared_secrets) = build_header(path, id, FLAG_REPLY)?;
+
+        let mut payload_key = [0u8; 32];
+        rand::thread_rng().fill(&mut payload_key);
+
+        let hop_keys = shared_secrets
+            .iter()
+            .map(|secret| derive_hop_key(secret, KEY_PAYLOAD))
+            .collect();
+
+        Ok((
+            Surb {
+                first_hop,
+                header,
+                payload_key,
+            },
+            SurbKeys {
+                id,
+                payload_key,
+                hop_keys,
+            },
+        ))
+    }
+}
+
+/// Size of a serialized `Surb`
+pub const SURB_SIZE: usize = 32 + 32 + BETA_SIZE + MAC_SIZE + 32;
+
+/// Single-use reply block (SURB)
+///
+/// Carries a pre-built header for a path back to the SURB creator. The
+/// holder learns only the first hop, never the creator's identity.
+pub struct Surb {
+    /// First hop of the reply path
+    pub first_hop: [u8; 32],
+    /// Pre-built header for the reply path
+    pub header: SphinxHeader,
+    /// Key the replier uses to encrypt the reply payload
+    payload_key: [u8; 32],
+}
+
+impl Surb {
+    /// Build the reply packet, consuming the SURB
+    ///
+    /// Returns the first hop to send the packet to.
+    pub fn reply(self, message: &[u8]) -> Result<([u8; 32], SphinxPacket), Box<dyn std::error::Error>> {
+        let mut payload = pad_payload(message)?;
+        lioness_encrypt(&self.payload_key, &mut payload)?;
+
+        Ok((
+            self.first_hop,
+            SphinxPacket {
+                header: self.header,
+                payload,
+            },
+        ))
+    }
+
+    /// Serialize for embedding in a payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SURB_SIZE);
+        bytes.extend_from_slice(&self.first_hop);
+        bytes.extend_from_slice(&self.header.alpha);
+        bytes.extend_from_slice(&self.header.beta);
+        bytes.extend_from_slice(&self.header.gamma);
+        bytes.extend_from_slice(&self.payload_key);
+        bytes
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if data.len() < SURB_SIZE {
+            return Err("Invalid SURB".into());
+        }
+
+        let gamma_start = 64 + BETA_SIZE;
+        Ok(Self {
+            first_hop: data[0..32].try_into()?,
             header: SphinxHeader {
-                alpha,
-                beta,
-                gamma,
+                alpha: data[32..64].try_into()?,
+                beta: data[64..gamma_start].to_vec(),
+                gamma: data[gamma_start..gamma_start + MAC_SIZE].try_into()?,
             },
-            payload: encrypted_payload,
+            payload_key: data[gamma_start + MAC_SIZE..SURB_SIZE].try_into()?,
         })
     }
+}
+
+/// Secrets kept by a SURB's creator to decrypt the reply
+pub struct SurbKeys {
+    /// Identifier the creator's node sees as `next_hop` when the reply arrives
+    pub id: [u8; 32],
+    payload_key: [u8; 32],
+    hop_keys: Vec<[u8; 32]>,
+}
 
-    /// Derive encryption key for a layer
-    fn derive_key(&self, session_key: &[u8; 32], layer: u8) -> [u8; 32] {
-        let mut hasher = Hasher::new();
-        hasher.update(session_key);
-        hasher.update(&[layer]);
-        let hash = hasher.finalize();
-        hash.as_bytes()[0..32].try_into().unwrap()
+impl SurbKeys {
+    /// Decrypt a reply's `final_payload`
+    pub fn decrypt_reply(&self, payload: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if payload.len() != PAYLOAD_SIZE {
+            return Err("Invalid payload length".into());
+        }
+
+        // Undo the hops' decryptions in reverse, then the replier's layer
+        let mut payload = payload.to_vec();
+        for key in self.hop_keys.iter().rev() {
+            lioness_encrypt(key, &mut payload)?;
+        }
+        lioness_decrypt(&self.payload_key, &mut payload)?;
+
+        if payload[..SECURITY_PARAMETER].iter().any(|&b| b != 0) {
+            return Err("Payload integrity check failed".into());
+        }
+        Ok(payload[SECURITY_PARAMETER..].to_vec())
     }
+}
 
-    /// Encrypt a layer with ChaCha20-Poly1305
-    fn encrypt_layer(&self, data: &mut [u8], key: &[u8; 32]) -> Result<(), Box<dyn std::error::Error>> {
-        // Simplified XOR encryption
-        // Real implementation would use proper AEAD
-        for (i, byte) in data.iter_mut().enumerate() {
-            *byte ^= key[i % 32];
+impl Drop for SurbKeys {
+    fn drop(&mut self) {
+        self.payload_key.zeroize();
+        for key in self.hop_keys.iter_mut() {
+            key.zeroize();
         }
-        
-        Ok(())
     }
 }
 
 /// Process a Sphinx packet at a mix node
 pub struct SphinxProcessor {
-    /// This node's secret key
+    /// This node's clamped X25519 secret key
+    ///
+    /// Kept as clamped bytes rather than a `Scalar` reduced mod ℓ, so the
+    /// cofactor stays cleared and a low-order alpha reveals nothing.
     secret_key: [u8; 32],
+    /// Tags of packets already processed, if replay protection is enabled
+    replay_cache: Option<Arc<ReplayCache>>,
+}
+
+impl Drop for SphinxProcessor {
+    fn drop(&mut self) {
+        self.secret_key.zeroize();
+    }
 }
 
 impl SphinxProcessor {
     /// Create new Sphinx processor
     pub fn new(secret_key: [u8; 32]) -> Self {
-        Self { secret_key }
+        Self {
+            secret_key: clamp(secret_key),
+            replay_cache: None,
+        }
+    }
+
+    /// Create a Sphinx processor that rejects replayed packets
+    pub fn with_replay_cache(secret_key: [u8; 32], replay_cache: Arc<ReplayCache>) -> Self {
+        let mut processor = Self::new(secret_key);
+        processor.replay_cache = Some(replay_cache);
+        processor
+    }
+
+    /// Public key that senders must use for this node in a path
+    pub fn public_key(&self) -> [u8; 32] {
+        MontgomeryPoint::mul_base_clamped(self.secret_key).to_bytes()
     }
 
     /// Process (peel one layer) from the packet
     pub fn process(&self, packet: SphinxPacket) -> Result<ProcessedPacket, Box<dyn std::error::Error>> {
-        // Verify MAC
-        let mut hasher = Hasher::new();
-        hasher.update(&packet.header.alpha);
-        hasher.update(&packet.header.beta);
-        hasher.update(&packet.payload);
-        let hash = hasher.finalize();
-        let expected_mac: [u8; MAC_SIZE] = hash.as_bytes()[0..MAC_SIZE].try_into()?;
-        
-        if expected_mac != packet.header.gamma {
+        if packet.header.beta.len() != BETA_SIZE {
+            return Err("Invalid routing info length".into());
+        }
+
+        // Only points of the prime-order subgroup can come from a sender;
+        // anything else would let the packet probe our key
+        let alpha = MontgomeryPoint(packet.header.alpha);
+        if alpha.to_edwards(0).map_or(true, |point| point.is_small_order()) {
+            return Err("Invalid group element".into());
+        }
+
+        // Shared secret via DH with our private key
+        let shared_secret = alpha.mul_clamped(self.secret_key).to_bytes();
+        if bool::from(shared_secret.ct_eq(&[0u8; 32])) {
+            return Err("Invalid group element".into());
+        }
+
+        // Verify this hop's MAC before decrypting anything
+        let mac_key = derive_hop_key(&shared_secret, KEY_MAC);
+        let expected_mac = compute_mac(&mac_key, &packet.header.beta);
+        if !bool::from(expected_mac[..].ct_eq(&packet.header.gamma[..])) {
             return Err("MAC verification failed".into());
         }
 
-        // Decrypt one layer of routing info
+        // Each shared secret may only be used once at this node
+        if let Some(cache) = &self.replay_cache {
+            if !cache.check_and_insert(replay_tag(&shared_secret)) {
+                return Err("Replayed packet".into());
+            }
+        }
+
+        // Decrypt one layer of routing info, shifting in zeros at the tail
         let mut beta = packet.header.beta.clone();
-        let key = self.derive_shared_key(&packet.header.alpha);
-        self.decrypt_layer(&mut beta, &key)?;
+        beta.resize(BETA_SIZE + ROUTING_INFO_SIZE, 0);
+        let key = derive_hop_key(&shared_secret, KEY_ROUTING);
+        xor_into(&mut beta, &keystream(&key, BETA_SIZE + ROUTING_INFO_SIZE));
 
-        // Extract routing info for this hop
+        // Extract routing info and the next hop's MAC
         let routing_info = RoutingInfo::from_bytes(&beta[0..ROUTING_INFO_SIZE])?;
+        let next_gamma: [u8; MAC_SIZE] = beta[GAMMA_OFFSET..GAMMA_OFFSET + MAC_SIZE].try_into()?;
+        let new_beta = beta[ROUTING_INFO_SIZE..].to_vec();
 
         // Decrypt one layer of payload
+        if packet.payload.len() != PAYLOAD_SIZE {
+            return Err("Invalid payload length".into());
+        }
         let mut payload = packet.payload.clone();
-        self.decrypt_layer(&mut payload, &key)?;
-
-        // Shift routing info (remove this hop's info)
-        let mut new_beta = beta[ROUTING_INFO_SIZE..].to_vec();
-        new_beta.resize(beta.len(), 0);
-
-        // Check if this is the final hop
-        let is_final = routing_info.flags == 1;
+        let key = derive_hop_key(&shared_secret, KEY_PAYLOAD);
+        lioness_decrypt(&key, &mut payload)?;
+
+        // Check if this is the final hop. Replies stay encrypted for the
+        // SURB creator, so only forward messages can be checked here.
+        let is_reply = routing_info.flags == FLAG_REPLY;
+        let is_final = routing_info.flags == FLAG_FINAL || is_reply;
+        if routing_info.flags == FLAG_FINAL && payload[..SECURITY_PARAMETER].iter().any(|&b| b != 0) {
+            return Err("Payload integrity check failed".into());
+        }
 
         Ok(ProcessedPacket {
             next_hop: routing_info.next_hop,
             delay: routing_info.delay,
             is_final,
+            is_reply,
             new_packet: if !is_final {
+                // Blind alpha so the next hop sees an unlinkable group element
+                let blinding = blinding_factor(&packet.header.alpha, &shared_secret);
+                let new_alpha = (alpha * blinding).to_bytes();
+
                 Some(SphinxPacket {
                     header: SphinxHeader {
-                        alpha: self.update_alpha(&packet.header.alpha),
+                        alpha: new_alpha,
                         beta: new_beta,
-                        gamma: packet.header.gamma, // Recompute in real impl
+                        gamma: next_gamma,
                     },
                     payload: payload.clone(),
                 })
             } else {
                 None
             },
-            final_payload: if is_final { Some(payload) } else { None },
+            final_payload: if is_reply {
+                Some(payload)
+            } else if is_final {
+                Some(payload[SECURITY_PARAMETER..].to_vec())
+            } else {
+                None
+            },
         })
     }
+}
+
+/// A header together with the shared secret of every hop on its path
+type HeaderWithSecrets = (SphinxHeader, Vec<[u8; 32]>);
+
+/// Build a header for `path`, returning it with every hop's shared secret
+///
+/// The last hop receives `final_hop` as its `next_hop` along with `final_flags`.
+fn build_header(
+    path: &[[u8; 32]],
+    final_hop: [u8; 32],
+    final_flags: u8,
+) -> Result<HeaderWithSecrets, Box<dyn std::error::Error>> {
+    if path.is_empty() || path.len() > MAX_HOPS {
+        return Err(format!("Path must have 1-{} hops", MAX_HOPS).into());
+    }
 
-    fn derive_shared_key(&self, alpha: &[u8; 32]) -> [u8; 32] {
-        let mut hasher = Hasher::new();
-        hasher.update(&self.secret_key);
-        hasher.update(alpha);
-        let hash = hasher.finalize();
-        hash.as_bytes()[0..32].try_into().unwrap()
+    // Fresh ephemeral exponent for this packet
+    let x = random_scalar();
+
+    // Derive the group element and shared secret seen by each hop
+    let (alpha, shared_secrets) = compute_shared_secrets(&x, path);
+
+    // Build routing info for each hop
+    let mut routing_infos = Vec::new();
+    for i in 0..path.len() {
+        let is_final = i == path.len() - 1;
+        routing_infos.push(RoutingInfo {
+            next_hop: if is_final { final_hop } else { path[i + 1] },
+            delay: 50 + (i as u32 * 10), // Increasing delays
+            flags: if is_final { final_flags } else { FLAG_RELAY },
+        });
     }
 
-    fn decrypt_layer(&self, data: &mut [u8], key: &[u8; 32]) -> Result<(), Box<dyn std::error::Error>> {
-        for (i, byte) in data.iter_mut().enumerate() {
-            *byte ^= key[i % 32];
+    // Tail that the hops' zero-shifting will reproduce, so later MACs verify
+    let filler = compute_filler(&shared_secrets);
+
+    // Encrypt routing info in reverse (onion layers), embedding each
+    // hop's MAC in the slot of the hop before it
+    let mut beta = vec![0u8; BETA_SIZE];
+    let mut gamma = [0u8; MAC_SIZE];
+    for (i, info) in routing_infos.iter().enumerate().rev() {
+        let mut layer = vec![0u8; ROUTING_INFO_SIZE];
+        let info_bytes = info.to_bytes();
+        layer[..info_bytes.len()].copy_from_slice(&info_bytes);
+        layer[GAMMA_OFFSET..GAMMA_OFFSET + MAC_SIZE].copy_from_slice(&gamma);
+        layer.extend_from_slice(&beta[..BETA_SIZE - ROUTING_INFO_SIZE]);
+
+        let key = derive_hop_key(&shared_secrets[i], KEY_ROUTING);
+        xor_into(&mut layer, &keystream(&key, BETA_SIZE));
+        if i == routing_infos.len() - 1 {
+            let start = BETA_SIZE - filler.len();
+            layer[start..].copy_from_slice(&filler);
         }
-        Ok(())
+
+        gamma = compute_mac(&derive_hop_key(&shared_secrets[i], KEY_MAC), &layer);
+        beta = layer;
     }
 
-    fn update_alpha(&self, alpha: &[u8; 32]) -> [u8; 32] {
-        // In real impl, do EC point multiplication
-        let mut hasher = Hasher::new();
-        hasher.update(alpha);
-        hasher.update(&self.secret_key);
-        let hash = hasher.finalize();
-        hash.as_bytes()[0..32].try_into().unwrap()
+    Ok((SphinxHeader { alpha, beta, gamma }, shared_secrets))
+}
+
+/// Prefix the message with the zero integrity tag and pad to `PAYLOAD_SIZE`
+fn pad_payload(message: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(format!("Message exceeds {} bytes", MAX_MESSAGE_SIZE).into());
+    }
+
+    let mut payload = vec![0u8; SECURITY_PARAMETER];
+    payload.extend_from_slice(message);
+    payload.resize(PAYLOAD_SIZE, 0); // Pad to fixed size
+    Ok(payload)
+}
+
+/// Generate a uniformly random X25519 scalar
+fn random_scalar() -> Scalar {
+    let mut wide = [0u8; 64];
+    rand::thread_rng().fill(&mut wide[..]);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Standard X25519 clamping of a secret key
+fn clamp(mut bytes: [u8; 32]) -> [u8; 32] {
+    bytes[0] &= 248;
+    bytes[31] &= 127;
+    bytes[31] |= 64;
+    bytes
+}
+
+/// Compute the first hop's alpha and the shared secret of every hop
+///
+/// Hop `i` sees `alpha_i = g^(x * b_0 * ... * b_{i-1})` and shares
+/// `s_i = y_i^(x * b_0 * ... * b_{i-1})` with the sender, where each
+/// blinding factor `b_j` is derived from `alpha_j` and `s_j`.
+fn compute_shared_secrets(x: &Scalar, path: &[[u8; 32]]) -> ([u8; 32], Vec<[u8; 32]>) {
+    let mut exponent = *x;
+    let mut shared_secrets = Vec::with_capacity(path.len());
+    let first_alpha = MontgomeryPoint::mul_base(&exponent).to_bytes();
+
+    for hop in path {
+        let alpha = MontgomeryPoint::mul_base(&exponent).to_bytes();
+        let shared_secret = (MontgomeryPoint(*hop) * exponent).to_bytes();
+        exponent *= blinding_factor(&alpha, &shared_secret);
+        shared_secrets.push(shared_secret);
+    }
+
+    (first_alpha, shared_secrets)
+}
+
+/// Blinding factor applied to alpha after a hop
+fn blinding_factor(alpha: &[u8; 32], shared_secret: &[u8; 32]) -> Scalar {
+    let mut hasher = Hasher::new_derive_key(KEY_BLINDING);
+    hasher.update(alpha);
+    hasher.update(shared_secret);
+    let mut wide = [0u8; 64];
+    hasher.finalize_xof().fill(&mut wide);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Compute the filler appended to the last hop's routing info
+///
+/// Each hop shifts `beta` left by one slot and appends decrypted zeros; the
+/// filler is exactly those bytes, so the sender can MAC what later hops see.
+fn compute_filler(shared_secrets: &[[u8; 32]]) -> Vec<u8> {
+    let mut filler = Vec::new();
+    for shared_secret in &shared_secrets[..shared_secrets.len() - 1] {
+        filler.extend_from_slice(&[0u8; ROUTING_INFO_SIZE]);
+        let key = derive_hop_key(shared_secret, KEY_ROUTING);
+        let stream = keystream(&key, BETA_SIZE + ROUTING_INFO_SIZE);
+        let offset = stream.len() - filler.len();
+        xor_into(&mut filler, &stream[offset..]);
+    }
+    filler
+}
+
+/// Derive a per-hop subkey from the shared secret
+fn derive_hop_key(shared_secret: &[u8; 32], context: &str) -> [u8; 32] {
+    blake3::derive_key(context, shared_secret)
+}
+
+/// Compute the per-hop header MAC over `beta`
+fn compute_mac(key: &[u8; 32], beta: &[u8]) -> [u8; MAC_SIZE] {
+    *blake3::keyed_hash(key, beta).as_bytes()
+}
+
+/// ChaCha20 keystream of `len` bytes for the given key
+///
+/// Every key is single-use (derived per hop and purpose), so a zero nonce is safe.
+fn keystream(key: &[u8; 32], len: usize) -> Vec<u8> {
+    let mut stream = vec![0u8; len];
+    let mut cipher = ChaCha20::new(key.into(), &[0u8; 12].into());
+    cipher.apply_keystream(&mut stream);
+    stream
+}
+
+/// XOR `stream` into `data`
+fn xor_into(data: &mut [u8], stream: &[u8]) {
+    for (byte, k) in data.iter_mut().zip(stream) {
+        *byte ^= k;
     }
 }
 
 /// Result of processing a Sphinx packet
 pub struct ProcessedPacket {
-    /// Address of next hop
+    /// Address of next hop (the SURB id for replies)
     pub next_hop: [u8; 32],
     /// Delay to apply (ms)
     pub delay: u32,
     /// Is this the final destination?
     pub is_final: bool,
+    /// Is this a SURB reply? Its payload must go to `SurbKeys::decrypt_reply`
+    pub is_reply: bool,
     /// Packet for next hop (if not final)
     pub new_packet: Option<SphinxPacket>,
-    /// Decrypted payload (if final)
+    /// Decrypted payload (if final; still encrypted for replies)
     pub final_payload: Option<Vec<u8>>,
 }
 
@@ -286,17 +589,205 @@ mod tests {
         assert_eq!(packet.payload.len(), PAYLOAD_SIZE);
     }
 
+    #[test]
+    fn test_oversized_message_rejected() {
+        let message = vec![0u8; PAYLOAD_SIZE];
+        assert!(SphinxBuilder::new(vec![[1u8; 32]], message).build().is_err());
+    }
+
+    fn processors(n: usize) -> Vec<SphinxProcessor> {
+        let mut rng = rand::thread_rng();
+        (0..n).map(|_| SphinxProcessor::new(rng.gen())).collect()
+    }
+
     #[test]
     fn test_sphinx_processing() {
-        let secret = [42u8; 32];
-        let processor = SphinxProcessor::new(secret);
-        
-        // Create test packet
-        let path = vec![[1u8; 32], [2u8; 32]];
+        let hops = processors(2);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
         let builder = SphinxBuilder::new(path, b"test".to_vec());
         let packet = builder.build().unwrap();
         
         // Process should not panic
-        let _ = processor.process(packet);
+        let _ = hops[0].process(packet);
+    }
+
+    #[test]
+    fn test_three_hop_decryption() {
+        let hops = processors(3);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let message = b"Three hop message".to_vec();
+
+        let mut packet = SphinxBuilder::new(path.clone(), message.clone()).build().unwrap();
+        let mut seen_alphas = vec![packet.header.alpha];
+
+        for (i, hop) in hops.iter().enumerate() {
+            let processed = hop.process(packet.clone()).unwrap();
+
+            if i < hops.len() - 1 {
+                assert!(!processed.is_final);
+                assert_eq!(processed.next_hop, path[i + 1]);
+                packet = processed.new_packet.unwrap();
+                seen_alphas.push(packet.header.alpha);
+            } else {
+                assert!(processed.is_final);
+                let payload = processed.final_payload.unwrap();
+                assert_eq!(&payload[..message.len()], &message[..]);
+            }
+        }
+
+        // Blinding gives every hop a distinct group element
+        assert_ne!(seen_alphas[0], seen_alphas[1]);
+        assert_ne!(seen_alphas[1], seen_alphas[2]);
+    }
+
+    #[test]
+    fn test_wrong_node_cannot_route() {
+        let hops = processors(3);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let packet = SphinxBuilder::new(path.clone(), b"msg".to_vec()).build().unwrap();
+
+        let outsider = processors(1).pop().unwrap();
+        assert!(outsider.process(packet).is_err());
+    }
+
+    #[test]
+    fn test_mac_chain_verifies_at_every_hop() {
+        let hops = processors(MAX_HOPS);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let mut packet = SphinxBuilder::new(path, b"longest path".to_vec()).build().unwrap();
+
+        for hop in &hops[..MAX_HOPS - 1] {
+            packet = hop.process(packet).unwrap().new_packet.unwrap();
+        }
+        assert!(hops[MAX_HOPS - 1].process(packet).unwrap().is_final);
+    }
+
+    #[test]
+    fn test_flipped_beta_bit_rejected_at_target_hop() {
+        let mut rng = rand::thread_rng();
+        let hops = processors(4);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+
+        for target in 0..hops.len() {
+            let mut packet = SphinxBuilder::new(path.clone(), b"msg".to_vec()).build().unwrap();
+            for hop in &hops[..target] {
+                packet = hop.process(packet).unwrap().new_packet.unwrap();
+            }
+
+            let bit = rng.gen_range(0..BETA_SIZE * 8);
+            packet.header.beta[bit / 8] ^= 1 << (bit % 8);
+
+            let err = hops[target].process(packet).err().unwrap();
+            assert_eq!(err.to_string(), "MAC verification failed");
+        }
+    }
+
+    #[test]
+    fn test_tampered_payload_rejected_at_exit() {
+        let hops = processors(3);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let mut packet = SphinxBuilder::new(path, b"tag me".to_vec()).build().unwrap();
+
+        packet = hops[0].process(packet).unwrap().new_packet.unwrap();
+        packet.payload[PAYLOAD_SIZE - 1] ^= 0x80;
+        packet = hops[1].process(packet).unwrap().new_packet.unwrap();
+
+        let err = hops[2].process(packet).err().unwrap();
+        assert_eq!(err.to_string(), "Payload integrity check failed");
+    }
+
+    #[test]
+    fn test_beta_looks_random() {
+        let hops = processors(2);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let packet = SphinxBuilder::new(path, b"msg".to_vec()).build().unwrap();
+
+        // The unused tail of beta must not be a recognisable pattern
+        let zeros = packet.header.beta.iter().filter(|&&b| b == 0).count();
+        assert!(zeros < BETA_SIZE / 16);
+    }
+
+    #[test]
+    fn test_surb_reply_round_trip() {
+        let mixes = processors(2);
+        let client = processors(1).pop().unwrap();
+        let reply_path = vec![mixes[0].public_key(), mixes[1].public_key(), client.public_key()];
+        let (surb, keys) = SphinxBuilder::build_surb(&reply_path).unwrap();
+
+        // Forward message to the recipient carries the SURB
+        let forward_hops = processors(3);
+        let forward_path: Vec<_> = forward_hops.iter().map(|p| p.public_key()).collect();
+        let mut packet = SphinxBuilder::new(forward_path, surb.to_bytes()).build().unwrap();
+        for hop in &forward_hops[..2] {
+            packet = hop.process(packet).unwrap().new_packet.unwrap();
+        }
+        let delivered = forward_hops[2].process(packet).unwrap().final_payload.unwrap();
+
+        // Recipient replies without learning who the client is
+        let surb = Surb::from_bytes(&delivered).unwrap();
+        assert_eq!(surb.first_hop, reply_path[0]);
+        let (first_hop, mut reply) = surb.reply(b"anonymous reply").unwrap();
+        assert_eq!(first_hop, mixes[0].public_key());
+
+        for mix in &mixes {
+            reply = mix.process(reply).unwrap().new_packet.unwrap();
+        }
+        let processed = client.process(reply).unwrap();
+        assert!(processed.is_final && processed.is_reply);
+        assert_eq!(processed.next_hop, keys.id);
+
+        let message = keys.decrypt_reply(&processed.final_payload.unwrap()).unwrap();
+        assert_eq!(&message[..15], b"anonymous reply");
+    }
+
+    #[test]
+    fn test_surb_hides_creator() {
+        let hops = processors(3);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let (surb, _keys) = SphinxBuilder::build_surb(&path).unwrap();
+
+        let bytes = surb.to_bytes();
+        assert_eq!(bytes.len(), SURB_SIZE);
+        assert!(!bytes.windows(32).any(|w| w == path[2]));
+    }
+
+    #[test]
+    fn test_tampered_reply_rejected() {
+        let hops = processors(2);
+        let path: Vec<_> = hops.iter().map(|p| p.public_key()).collect();
+        let (surb, keys) = SphinxBuilder::build_surb(&path).unwrap();
+
+        let (_, mut reply) = surb.reply(b"reply").unwrap();
+        reply.payload[10] ^= 1;
+        reply = hops[0].process(reply).unwrap().new_packet.unwrap();
+        let payload = hops[1].process(reply).unwrap().final_payload.unwrap();
+
+        assert!(keys.decrypt_reply(&payload).is_err());
+    }
+
+    #[test]
+    fn test_replayed_packet_rejected() {
+        let cache = Arc::new(ReplayCache::new(64));
+        let hop = SphinxProcessor::with_replay_cache(rand::thread_rng().gen(), cache);
+        let packet = SphinxBuilder::new(vec![hop.public_key()], b"once".to_vec()).build().unwrap();
+
+        assert!(hop.process(packet.clone()).is_ok());
+        let err = hop.process(packet).err().unwrap();
+        assert_eq!(err.to_string(), "Replayed packet");
+    }
+
+    #[test]
+    fn test_low_order_alpha_rejected() {
+        let hop = processors(1).remove(0);
+        let mut packet = SphinxBuilder::new(vec![hop.public_key()], b"probe".to_vec()).build().unwrap();
+
+        // u = 0 and u = 1 are points of order 2 and 4
+        let mut order_four = [0u8; 32];
+        order_four[0] = 1;
+        for alpha in [[0u8; 32], order_four] {
+            packet.header.alpha = alpha;
+            let err = hop.process(packet.clone()).err().unwrap();
+            assert_eq!(err.to_string(), "Invalid group element");
+        }
     }
 }
@@ -201,6 +201,7 @@ mod tests {
                 stake: 1000,
                 address: format!("node-{}", i),
                 public_key_bytes: vec![i; 1568],
+                epoch_keys: Vec::new(),
             }
         }).collect();
         
@@ -3,7 +3,7 @@
 //! ⚠️ FOR AUTHORIZED SECURITY RESEARCH ONLY
 
 use crate::crypto::symmetric::{encrypt_aead, decrypt_aead, generate_nonce};
-use crate::error::{AetherError, Result};
+use crate::error::{AetherError, PacketError, Result};
 use rand::Rng;
 
 /// Traffic morphing engine
@@ -96,7 +96,7 @@ impl TrafficMorpher {
     fn parse_tls(&self, morphed: &[u8]) -> Result<Vec<u8>> {
         // Verify it looks like TLS
         if morphed.len() < 5 || morphed[0] != 23 {
-            return Err(AetherError::Packet("Invalid TLS header".to_string()));
+            return Err(AetherError::Packet(PacketError::Malformed("Invalid TLS header".to_string())));
         }
         
         // Extract length
@@ -153,7 +153,7 @@ impl TrafficMorpher {
     /// Parse SSH packet
     fn parse_ssh(&self, morphed: &[u8]) -> Result<Vec<u8>> {
         if morphed.len() < 5 {
-            return Err(AetherError::Packet("Invalid SSH packet".to_string()));
+            return Err(AetherError::Packet(PacketError::Malformed("Invalid SSH packet".to_string())));
         }
         
         // Extract packet length and padding
@@ -38,15 +38,67 @@ async fn test_end_to_end_packet_flow() {
     let message = b"Test message for anonymity network";
     let packet = OutfoxPacket::new(message, &route).unwrap();
     
-    // Verify packet properties
-    assert_eq!(packet.metadata.layer, 0);
-    assert!(packet.verify_integrity());
-    
     // Serialize and deserialize
     let bytes = packet.to_bytes().unwrap();
+    assert_eq!(bytes.len(), aether_network::MAX_PACKET_SIZE);
     let decoded = OutfoxPacket::from_bytes(&bytes).unwrap();
     
-    assert_eq!(packet.metadata.packet_id, decoded.metadata.packet_id);
+    assert_eq!(packet.header, decoded.header);
+    assert_eq!(packet.payload, decoded.payload);
+}
+
+#[tokio::test]
+async fn test_packet_traverses_five_networked_layers() {
+    use aether_network::crypto::kyber::PublicKey;
+    use tokio::io::AsyncWriteExt;
+    
+    // No node cover, so each node processes exactly the injected packet
+    let mut config = AetherConfig::default();
+    config.cover_traffic_ratio = 0.0;
+    let config = Arc::new(config);
+    
+    // Reserve a free localhost port for each layer
+    let mut nodes = Vec::new();
+    for layer in 1..=5 {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let node = MixNode::new(
+            layer,
+            NodeRole::MixNode,
+            1000,
+            format!("127.0.0.1:{}", port),
+            Arc::clone(&config),
+        ).unwrap();
+        node.run().await.unwrap();
+        nodes.push(node);
+    }
+    for node in &nodes {
+        for peer in &nodes {
+            node.add_peer(&peer.info).await;
+        }
+    }
+    
+    let route: Vec<_> = nodes.iter()
+        .map(|n| PublicKey::from_bytes(&n.info.public_key_bytes).unwrap())
+        .collect();
+    let packet = OutfoxPacket::new(b"over the wire", &route).unwrap();
+    
+    // Inject at the entry node like a client would
+    let mut stream = tokio::net::TcpStream::connect(&nodes[0].info.address).await.unwrap();
+    stream.write_all(&packet.to_bytes().unwrap()).await.unwrap();
+    
+    let message = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            if let Some(message) = nodes[4].receive_message().await {
+                return message;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }).await.expect("packet did not reach the exit");
+    
+    assert_eq!(message, b"over the wire");
+    for node in &nodes {
+        assert_eq!(node.get_stats().await.packets_processed, 1);
+    }
 }
 
 #[test]
@@ -99,7 +151,7 @@ fn test_reputation_system() {
 fn test_traffic_shaping() {
     use aether_network::mixnet::traffic::TrafficShaper;
     
-    let mut shaper = TrafficShaper::new(100.0, 0.4);
+    let mut shaper = TrafficShaper::new(4, std::time::Duration::from_millis(40));
     
     // Record packets
     for i in 0..100 {